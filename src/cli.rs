@@ -0,0 +1,179 @@
+//! Subcommands which operate on the pipewire graph once without starting the ui
+
+/// A port, given as `<node>:<port>` using the name of the node in the graph and the `port.name` property
+#[derive(Debug, Clone)]
+pub struct PortPath {
+    pub node: String,
+    pub port: String,
+}
+
+impl std::str::FromStr for PortPath {
+    type Err = String;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        // Node names may contain colons, port names usually don't
+        match path.rsplit_once(':') {
+            Some((node, port)) if !node.is_empty() && !port.is_empty() => Ok(Self {
+                node: node.to_string(),
+                port: port.to_string(),
+            }),
+            _ => Err(format!("Expected <node>:<port>, got {:?}", path)),
+        }
+    }
+}
+
+impl std::fmt::Display for PortPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.node, self.port)
+    }
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Connect {
+        output: PortPath,
+        input: PortPath,
+        /// Link even if it closes a feedback loop, which is refused otherwise
+        feedback: bool,
+    },
+    Disconnect {
+        output: PortPath,
+        input: PortPath,
+    },
+    /// Lists ports, or links if `links` is set
+    List {
+        links: bool,
+        json: bool,
+    },
+}
+
+pub const USAGE: &str = "\
+Usage:
+    pw-viz                                                    Start the graph editor
+    pw-viz connect [--feedback] <node>:<port> <node>:<port>   Link an output port to an input port,
+                                                              --feedback allows closing a feedback loop
+    pw-viz disconnect <node>:<port> <node>:<port>             Remove the link between two ports
+    pw-viz ls [--links] [--json]                              List ports, or links with --links";
+
+/// Parses the command line arguments excluding the program name,
+/// returns `None` if the ui should be started
+pub fn parse(args: &[String]) -> Result<Option<Command>, String> {
+    let (subcommand, args) = match args.split_first() {
+        Some((subcommand, args)) => (subcommand.as_str(), args),
+        None => return Ok(None),
+    };
+
+    let port_pair = |args: &[String]| -> Result<(PortPath, PortPath), String> {
+        match args {
+            [output, input] => Ok((output.parse()?, input.parse()?)),
+            _ => Err(format!(
+                "{} expects an output and an input port",
+                subcommand
+            )),
+        }
+    };
+
+    let command = match subcommand {
+        "connect" => {
+            let feedback = args.iter().any(|arg| arg == "--feedback");
+            let ports = args
+                .iter()
+                .filter(|arg| *arg != "--feedback")
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let (output, input) = port_pair(&ports)?;
+            Command::Connect {
+                output,
+                input,
+                feedback,
+            }
+        }
+        "disconnect" => {
+            let (output, input) = port_pair(args)?;
+            Command::Disconnect { output, input }
+        }
+        "ls" => {
+            let mut links = false;
+            let mut json = false;
+            for arg in args {
+                match arg.as_str() {
+                    "--links" | "-l" => links = true,
+                    "--json" | "-j" => json = true,
+                    _ => return Err(format!("Unknown option {:?}", arg)),
+                }
+            }
+            Command::List { links, json }
+        }
+        _ => return Err(format!("Unknown command {:?}", subcommand)),
+    };
+
+    Ok(Some(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn port_path_table() {
+        for (path, node, port) in [
+            ("Firefox:output_FL", "Firefox", "output_FL"),
+            // Only the last colon separates the port, node names may contain colons
+            (
+                "alsa_output.pci-0000:00:1f.3.analog-stereo:playback_FL",
+                "alsa_output.pci-0000:00:1f.3.analog-stereo",
+                "playback_FL",
+            ),
+            (
+                "Game: Voice chat:input_MONO",
+                "Game: Voice chat",
+                "input_MONO",
+            ),
+            ("Speakers:monitor:FL", "Speakers:monitor", "FL"),
+        ] {
+            let parsed = path.parse::<PortPath>().unwrap();
+            assert_eq!((parsed.node.as_str(), parsed.port.as_str()), (node, port));
+            assert_eq!(parsed.to_string(), path);
+        }
+    }
+
+    #[test]
+    fn port_path_needs_a_node_and_a_port() {
+        for path in ["", "Firefox", ":output_FL", "Firefox:", ":"] {
+            assert!(
+                path.parse::<PortPath>().is_err(),
+                "{:?} should not parse",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn connect_takes_a_feedback_flag() {
+        for (arguments, expected) in [
+            (&["connect", "a:out", "b:in"][..], false),
+            (&["connect", "--feedback", "a:out", "b:in"][..], true),
+            (&["connect", "a:out", "b:in", "--feedback"][..], true),
+        ] {
+            match parse(&args(arguments)) {
+                Ok(Some(Command::Connect {
+                    output,
+                    input,
+                    feedback,
+                })) => {
+                    assert_eq!(output.to_string(), "a:out");
+                    assert_eq!(input.to_string(), "b:in");
+                    assert_eq!(feedback, expected, "{:?}", arguments);
+                }
+                result => panic!("{:?} parsed as {:?}", arguments, result),
+            }
+        }
+
+        assert!(parse(&args(&["connect", "--feedback", "a:out"])).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Control interface for a running instance, a Unix socket speaking newline delimited JSON-RPC 2.0
+//!
+//! Every request is forwarded to the ui thread, which owns the graph, and answered once it has been handled.
+//! For example `{"jsonrpc": "2.0", "id": 1, "method": "connect", "params": {"output": "Firefox:output_FL", "input": "Speakers:playback_FL"}}`
+//! Ports are given as `<node>:<port>` paths, the same as on the command line
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use eframe::epi;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// How long a client waits for the ui thread to answer a request
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum IpcError {
+    MethodNotFound(String),
+    InvalidParams(String),
+    Failed(String),
+}
+
+impl IpcError {
+    fn code(&self) -> i32 {
+        match self {
+            IpcError::MethodNotFound(_) => -32601,
+            IpcError::InvalidParams(_) => -32602,
+            IpcError::Failed(_) => -32000,
+        }
+    }
+    fn message(&self) -> &str {
+        match self {
+            IpcError::MethodNotFound(message)
+            | IpcError::InvalidParams(message)
+            | IpcError::Failed(message) => message,
+        }
+    }
+}
+
+/// A request waiting to be handled by the ui thread
+pub struct IpcRequest {
+    pub method: String,
+    pub params: Value,
+    reply: Sender<Result<Value, IpcError>>,
+}
+
+impl IpcRequest {
+    pub fn reply(self, result: Result<Value, IpcError>) {
+        // The client may have disconnected in the meantime
+        let _ = self.reply.send(result);
+    }
+    /// Deserializes the params of the request
+    pub fn params<T: serde::de::DeserializeOwned>(&self) -> Result<T, IpcError> {
+        serde_json::from_value(self.params.clone())
+            .map_err(|err| IpcError::InvalidParams(err.to_string()))
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// `$XDG_RUNTIME_DIR/pw-viz.sock`, `None` if there is no runtime directory.
+/// Anyone who can connect to the socket can change the graph, so it isn't put in a shared directory
+pub fn default_socket_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|runtime_dir| {
+        PathBuf::from(runtime_dir).join(concat!(env!("CARGO_PKG_NAME"), ".sock"))
+    })
+}
+
+/// Starts listening on the socket, requests are sent to the ui thread which is woken up using `frame`
+pub fn spawn(path: &Path, sender: Sender<IpcRequest>, frame: epi::Frame) -> std::io::Result<()> {
+    // A socket left behind by an instance that didn't exit cleanly would make binding fail
+    if path.exists() && UnixStream::connect(path).is_err() {
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    log::info!("Listening for control requests on {}", path.display());
+
+    thread::Builder::new()
+        .name("IPC".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        let frame = frame.clone();
+                        thread::spawn(move || handle_client(stream, sender, frame));
+                    }
+                    Err(err) => log::error!("Failed to accept IPC connection: {}", err),
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+fn handle_client(stream: UnixStream, sender: Sender<IpcRequest>, frame: epi::Frame) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(err) => {
+            log::error!("Failed to clone IPC stream: {}", err);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let response = match serde_json::from_str::<RpcRequest>(&line) {
+            Ok(request) => {
+                let result = forward(request.method, request.params, &sender, &frame);
+                response(request.id.unwrap_or(Value::Null), result)
+            }
+            Err(err) => json!({
+                "jsonrpc": "2.0",
+                "id": Value::Null,
+                "error": { "code": -32700, "message": err.to_string() },
+            }),
+        };
+
+        if writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+/// Hands the request to the ui thread and waits for its result
+fn forward(
+    method: String,
+    params: Value,
+    sender: &Sender<IpcRequest>,
+    frame: &epi::Frame,
+) -> Result<Value, IpcError> {
+    let (reply, result) = mpsc::channel();
+
+    sender
+        .send(IpcRequest {
+            method,
+            params,
+            reply,
+        })
+        .map_err(|_| IpcError::Failed("The ui has exited".to_string()))?;
+    frame.request_repaint();
+
+    result
+        .recv_timeout(REPLY_TIMEOUT)
+        .map_err(|_| IpcError::Failed("Timed out waiting for the ui".to_string()))?
+}
+
+fn response(id: Value, result: Result<Value, IpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": err.code(), "message": err.message() },
+        }),
+    }
+}
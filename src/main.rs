@@ -1,7 +1,10 @@
 use simple_logger::SimpleLogger;
 use std::{rc::Rc, thread};
 
+mod cli;
+mod ipc;
 mod pipewire_impl;
+mod script;
 mod ui;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -9,6 +12,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("Failed to init logger");
     }
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match cli::parse(&args) {
+        Ok(Some(command)) => {
+            if let Err(err) = pipewire_impl::run_command(&command) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    }
+
     // The UI (main thread) and PipeWire client run on different threads, communication between the threads is facilitated using message passing
     let (sender, receiver) = std::sync::mpsc::channel();
     let (pwsender, pwreciever) = pipewire::channel::channel();
@@ -0,0 +1,835 @@
+//! Reading and writing of `pipewire.conf.d` fragments
+//!
+//! Virtual devices(null sinks/sources) are written as `adapter` entries of `context.objects` and loopbacks
+//! as `libpipewire-module-loopback` entries of `context.modules`, so pipewire recreates them when it starts.
+//! Links between the nodes created by the fragment are written as `link-factory` entries of `context.objects`.
+//!
+//! Devices and streams don't exist yet when pipewire loads the fragment, so links to them would be dropped.
+//! They are kept in a `pw-viz.links` section instead, which is only restored once the fragment is imported into pw-viz,
+//! the links are then made as their ports appear.
+//! Port labels are kept in a `pw-viz.port-labels` section, pipewire itself ignores both sections.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    path::PathBuf,
+};
+
+use super::state::{GlobalObject, State};
+
+/// Node properties that are needed to recreate a virtual device
+const VIRTUAL_DEVICE_KEYS: &[&str] = &[
+    "factory.name",
+    "node.name",
+    "node.description",
+    "media.class",
+    "audio.channels",
+    "audio.position",
+    "monitor.channel-volumes",
+    "node.virtual",
+];
+
+/// Properties of each side of a loopback that are needed to recreate it
+const LOOPBACK_KEYS: &[&str] = &[
+    "node.name",
+    "node.description",
+    "media.class",
+    "audio.channels",
+    "audio.position",
+    "node.passive",
+    "target.object",
+];
+
+const NULL_SINK_FACTORY: &str = "support.null-audio-sink";
+
+/// Both nodes of a loopback share a `node.link-group` starting with this
+const LOOPBACK_LINK_GROUP: &str = "loopback-";
+
+/// Section holding the links which pipewire can't make when loading the fragment
+const LINKS_SECTION: &str = "pw-viz.links";
+
+/// Section holding the port labels, keyed by `<node>:<port>` paths
+const PORT_LABELS_SECTION: &str = "pw-viz.port-labels";
+
+/// A link between two ports given by the `node.name` and `port.name` of each end
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfLink {
+    pub output_node: String,
+    pub output_port: String,
+    pub input_node: String,
+    pub input_port: String,
+}
+
+impl std::fmt::Display for ConfLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{} -> {}:{}",
+            self.output_node, self.output_port, self.input_node, self.input_port
+        )
+    }
+}
+
+/// An object read from a fragment
+#[derive(Debug)]
+pub enum ConfObject {
+    Link(ConfLink),
+    VirtualDevice {
+        props: Vec<(String, String)>,
+    },
+    /// Label of the port at a `<node>:<port>` path
+    PortLabel {
+        path: String,
+        label: String,
+    },
+}
+
+/// `$XDG_CONFIG_HOME`, falling back to `~/.config`
+pub fn xdg_config_dir() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default()
+}
+
+/// `$XDG_CONFIG_HOME/pipewire/pipewire.conf.d/pw-viz.conf`
+pub fn default_path() -> PathBuf {
+    xdg_config_dir()
+        .join("pipewire")
+        .join("pipewire.conf.d")
+        .join("pw-viz.conf")
+}
+
+fn node_props(state: &State, node_id: u32) -> Option<&HashMap<String, String>> {
+    match state.get(node_id)? {
+        GlobalObject::Node { props, .. } => Some(props),
+        _ => None,
+    }
+}
+
+fn port_info(state: &State, port_id: u32) -> Option<(u32, &str)> {
+    match state.get(port_id)? {
+        GlobalObject::Port { node_id, name, .. } => Some((*node_id, name)),
+        _ => None,
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn write_props(out: &mut String, indent: &str, props: &HashMap<String, String>, keys: &[&str]) {
+    for key in keys {
+        if let Some(value) = props.get(*key) {
+            writeln!(out, "{}{} = {}", indent, key, quote(value)).unwrap();
+        }
+    }
+}
+
+fn write_link(out: &mut String, indent: &str, link: &ConfLink) {
+    for (key, value) in [
+        ("link.output.node", &link.output_node),
+        ("link.output.port", &link.output_port),
+        ("link.input.node", &link.input_node),
+        ("link.input.port", &link.input_port),
+    ] {
+        writeln!(out, "{}{} = {}", indent, key, quote(value)).unwrap();
+    }
+}
+
+/// The capture side of a loopback is its sink, the playback side its source
+fn is_loopback_capture(props: &HashMap<String, String>) -> bool {
+    let media_class = props.get("media.class").map_or("", String::as_str);
+    media_class.contains("Input") || media_class.ends_with("Sink")
+}
+
+/// Generates a fragment containing all the virtual devices, loopbacks and links currently present in the graph,
+/// along with the labels given to ports
+pub fn export(state: &State, port_labels: &HashMap<String, String>) -> String {
+    let mut ids = state.iter().map(|(id, _)| id).collect::<Vec<_>>();
+    ids.sort_unstable();
+    let nodes = ids
+        .iter()
+        .filter_map(|&id| Some((id, node_props(state, id)?)));
+
+    // Nodes which exist as soon as pipewire has loaded the fragment, so that links between them can be made there
+    let mut created = HashSet::new();
+
+    let mut objects = Vec::new();
+    for (id, props) in nodes.clone() {
+        if props.get("factory.name").map(String::as_str) != Some(NULL_SINK_FACTORY) {
+            continue;
+        }
+        created.insert(id);
+
+        let mut args = String::new();
+        write_props(&mut args, "            ", props, VIRTUAL_DEVICE_KEYS);
+        objects.push(format!(
+            "    {{ factory = adapter\n        args = {{\n{}        }}\n    }}\n",
+            args
+        ));
+    }
+
+    // The capture and playback nodes of every loopback, grouped by their link group
+    let mut loopbacks = BTreeMap::<&str, (Option<_>, Option<_>)>::new();
+    for (id, props) in nodes {
+        let link_group = match props.get("node.link-group") {
+            Some(link_group) if link_group.starts_with(LOOPBACK_LINK_GROUP) => link_group,
+            _ => continue,
+        };
+        let loopback = loopbacks.entry(link_group).or_default();
+        if is_loopback_capture(props) {
+            loopback.0 = Some((id, props));
+        } else {
+            loopback.1 = Some((id, props));
+        }
+    }
+
+    let mut modules = Vec::new();
+    for (link_group, loopback) in loopbacks {
+        let ((capture_id, capture), (playback_id, playback)) = match loopback {
+            (Some(capture), Some(playback)) => (capture, playback),
+            _ => {
+                log::warn!("Skipping loopback {} which is missing a side", link_group);
+                continue;
+            }
+        };
+        created.insert(capture_id);
+        created.insert(playback_id);
+
+        let mut args = String::new();
+        for (name, props) in [("capture.props", capture), ("playback.props", playback)] {
+            writeln!(args, "            {} = {{", name).unwrap();
+            write_props(&mut args, "                ", props, LOOPBACK_KEYS);
+            writeln!(args, "            }}").unwrap();
+        }
+        modules.push(format!(
+            "    {{ name = libpipewire-module-loopback\n        args = {{\n{}        }}\n    }}\n",
+            args
+        ));
+    }
+
+    let mut links = Vec::new();
+    for &id in ids.iter() {
+        let (from_port, to_port) = match state.get(id) {
+            Some(GlobalObject::Link { from_port, to_port }) => (*from_port, *to_port),
+            _ => continue,
+        };
+
+        let link = port_info(state, from_port).zip(port_info(state, to_port));
+        let link = link.and_then(|((from_node, from_port), (to_node, to_port))| {
+            let link = ConfLink {
+                output_node: node_props(state, from_node)?.get("node.name")?.clone(),
+                output_port: from_port.to_string(),
+                input_node: node_props(state, to_node)?.get("node.name")?.clone(),
+                input_port: to_port.to_string(),
+            };
+
+            Some((
+                created.contains(&from_node) && created.contains(&to_node),
+                link,
+            ))
+        });
+
+        let mut args = String::new();
+        match link {
+            Some((true, link)) => {
+                write_link(&mut args, "            ", &link);
+                objects.push(format!(
+                    "    {{ factory = link-factory\n        args = {{\n{}            object.linger = true\n        }}\n        flags = [ nofail ]\n    }}\n",
+                    args
+                ));
+            }
+            Some((false, link)) => {
+                write_link(&mut args, "        ", &link);
+                links.push(format!("    {{\n{}    }}\n", args));
+            }
+            None => log::warn!("Skipping link {} with unregistered ports", id),
+        }
+    }
+
+    let mut fragment = format!(
+        "# Generated by {} {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    if !modules.is_empty() {
+        writeln!(fragment, "context.modules = [\n{}]", modules.concat()).unwrap();
+    }
+    writeln!(fragment, "context.objects = [\n{}]", objects.concat()).unwrap();
+    if !links.is_empty() {
+        writeln!(fragment, "{} = [\n{}]", LINKS_SECTION, links.concat()).unwrap();
+    }
+
+    if !port_labels.is_empty() {
+        let mut port_labels = port_labels.iter().collect::<Vec<_>>();
+        port_labels.sort();
+
+        writeln!(fragment, "{} = {{", PORT_LABELS_SECTION).unwrap();
+        for (path, label) in port_labels {
+            writeln!(fragment, "    {} = {}", quote(path), quote(label)).unwrap();
+        }
+        writeln!(fragment, "}}").unwrap();
+    }
+
+    fragment
+}
+
+fn array<'a>(root: &'a Value, key: &str) -> Result<&'a [Value], String> {
+    match root.get(key) {
+        Some(Value::Array(values)) => Ok(values.as_slice()),
+        Some(_) => Err(format!("{} is not an array", key)),
+        None => Ok(&[][..]),
+    }
+}
+
+fn conf_link(args: &Value) -> Result<ConfLink, String> {
+    let get = |key| {
+        args.get(key)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| format!("link is missing {}", key))
+    };
+
+    Ok(ConfLink {
+        output_node: get("link.output.node")?,
+        output_port: get("link.output.port")?,
+        input_node: get("link.input.node")?,
+        input_port: get("link.input.port")?,
+    })
+}
+
+/// Reads the virtual devices and links from the `context.objects` section of a fragment,
+/// the links left to pw-viz and the port labels.
+/// Loopbacks are only loaded by pipewire itself, as `context.modules` can't be loaded into a running instance
+pub fn import(source: &str) -> Result<Vec<ConfObject>, String> {
+    let root = Parser::new(source).parse_root()?;
+
+    let mut result = Vec::new();
+    for object in array(&root, "context.objects")? {
+        let args = match object.get("args") {
+            Some(args) => args,
+            None => continue,
+        };
+
+        match object.get("factory").and_then(Value::as_str) {
+            Some("link-factory") => result.push(ConfObject::Link(conf_link(args)?)),
+            Some("adapter")
+                if args.get("factory.name").and_then(Value::as_str) == Some(NULL_SINK_FACTORY) =>
+            {
+                let props = match args {
+                    Value::Object(entries) => entries
+                        .iter()
+                        .filter_map(|(key, value)| Some((key.clone(), value.to_conf_string()?)))
+                        .collect(),
+                    _ => continue,
+                };
+
+                result.push(ConfObject::VirtualDevice { props });
+            }
+            factory => log::warn!("Ignoring object with factory {:?}", factory),
+        }
+    }
+
+    for link in array(&root, LINKS_SECTION)? {
+        result.push(ConfObject::Link(conf_link(link)?));
+    }
+
+    match root.get(PORT_LABELS_SECTION) {
+        Some(Value::Object(labels)) => {
+            for (path, label) in labels {
+                match label.as_str() {
+                    Some(label) => result.push(ConfObject::PortLabel {
+                        path: path.clone(),
+                        label: label.to_string(),
+                    }),
+                    None => log::warn!("Ignoring label of {} which isn't a string", path),
+                }
+            }
+        }
+        Some(_) => return Err(format!("{} is not an object", PORT_LABELS_SECTION)),
+        None => {}
+    }
+
+    Ok(result)
+}
+
+/// A SPA-JSON value, strings, numbers and barewords are all kept as strings
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries
+                .iter()
+                .rev()
+                .find(|(entry_key, _)| entry_key == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+    /// Flattens arrays of strings into a comma separated list, which is how pipewire stores them in properties
+    fn to_conf_string(&self) -> Option<String> {
+        match self {
+            Value::String(value) => Some(value.clone()),
+            Value::Array(values) => values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+                .map(|values| values.join(",")),
+            Value::Object(_) => None,
+        }
+    }
+}
+
+/// A parser for the relaxed JSON dialect used by pipewire config files
+/// Keys don't need to be quoted, `=` can be used instead of `:`, commas are optional and `#` starts a comment
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            chars: source.chars().peekable(),
+        }
+    }
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c == '#' {
+                while !matches!(self.chars.next(), Some('\n') | None) {}
+            } else if c.is_whitespace() || c == ',' {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+    /// The top level of a config file is an object without braces
+    fn parse_root(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'{') {
+            return self.parse_value();
+        }
+        self.parse_entries(None)
+    }
+    fn parse_entries(&mut self, end: Option<char>) -> Result<Value, String> {
+        let mut entries = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some(&c) if Some(c) == end => {
+                    self.chars.next();
+                    break;
+                }
+                None if end.is_none() => break,
+                None => return Err("unexpected end of file".to_string()),
+                _ => {}
+            }
+
+            let key = match self.parse_value()? {
+                Value::String(key) => key,
+                _ => return Err("object keys must be strings".to_string()),
+            };
+
+            self.skip_whitespace();
+            if matches!(self.chars.peek(), Some('=') | Some(':')) {
+                self.chars.next();
+            }
+
+            entries.push((key, self.parse_value()?));
+        }
+        Ok(Value::Object(entries))
+    }
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => {
+                self.chars.next();
+                self.parse_entries(Some('}'))
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.chars.peek() {
+                        Some(']') => {
+                            self.chars.next();
+                            break;
+                        }
+                        None => return Err("unterminated array".to_string()),
+                        _ => values.push(self.parse_value()?),
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            Some('"') => {
+                self.chars.next();
+                let mut value = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match self.chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(c) => value.push(c),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                Ok(Value::String(value))
+            }
+            Some(_) => {
+                let mut value = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_whitespace() || "{}[]=:,\"#".contains(c) {
+                        break;
+                    }
+                    value.push(c);
+                    self.chars.next();
+                }
+                if value.is_empty() {
+                    return Err(format!("unexpected character {:?}", self.chars.peek()));
+                }
+                Ok(Value::String(value))
+            }
+            None => Err("unexpected end of file".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    fn parse(source: &str) -> Result<Value, String> {
+        Parser::new(source).parse_root()
+    }
+
+    fn node(name: &str, props: &[(&str, &str)]) -> GlobalObject {
+        GlobalObject::Node {
+            name: name.to_string(),
+            props: props
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn port(node_id: u32, id: u32, name: &str) -> GlobalObject {
+        GlobalObject::Port {
+            node_name: String::new(),
+            node_id,
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    fn header() -> String {
+        format!(
+            "# Generated by {} {}\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+
+    #[test]
+    fn parses_relaxed_syntax() {
+        let source = r#"
+            # Keys can be barewords or quoted, with = or : and optional commas
+            context.properties = { default.clock.rate = 48000, "log.level": 2 }
+            context.modules = [ a b, "c d" ]
+        "#;
+
+        assert_eq!(
+            parse(source),
+            Ok(Value::Object(vec![
+                (
+                    "context.properties".to_string(),
+                    Value::Object(vec![
+                        ("default.clock.rate".to_string(), string("48000")),
+                        ("log.level".to_string(), string("2")),
+                    ])
+                ),
+                (
+                    "context.modules".to_string(),
+                    Value::Array(vec![string("a"), string("b"), string("c d")])
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn parses_braced_root_and_escapes() {
+        assert_eq!(
+            parse(r#"{ name = "say \"hi\"\n" }"#),
+            Ok(Value::Object(vec![(
+                "name".to_string(),
+                string("say \"hi\"\n")
+            )]))
+        );
+    }
+
+    #[test]
+    fn comments_end_barewords() {
+        assert_eq!(
+            parse("key = value# comment\nother = 1"),
+            Ok(Value::Object(vec![
+                ("key".to_string(), string("value")),
+                ("other".to_string(), string("1")),
+            ]))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for source in [
+            "key = \"unterminated",
+            "key = [ a b",
+            "key = { a = b",
+            "key = ",
+            "[ a ] = b",
+            "key = }",
+        ] {
+            assert!(parse(source).is_err(), "{:?} should not parse", source);
+        }
+    }
+
+    #[test]
+    fn later_keys_override_earlier_ones() {
+        let value = parse("a = 1 a = 2").unwrap();
+        assert_eq!(value.get("a"), Some(&string("2")));
+    }
+
+    #[test]
+    fn arrays_are_flattened_into_properties() {
+        let value = parse("audio.position = [ FL FR ]").unwrap();
+        assert_eq!(
+            value.get("audio.position").and_then(Value::to_conf_string),
+            Some("FL,FR".to_string())
+        );
+    }
+
+    #[test]
+    fn export_can_be_imported() {
+        let mut state = State::new();
+
+        state.add(
+            1,
+            node(
+                "Virtual sink",
+                &[
+                    ("factory.name", NULL_SINK_FACTORY),
+                    ("node.name", "virtual_sink"),
+                    ("node.description", "Virtual \"sink\""),
+                    ("media.class", "Audio/Sink"),
+                ],
+            ),
+        );
+        state.add(2, node("Player", &[("node.name", "player")]));
+        state.add(3, port(2, 3, "output_FL"));
+        state.add(4, port(1, 4, "playback_FL"));
+        state.add(
+            5,
+            GlobalObject::Link {
+                from_port: 3,
+                to_port: 4,
+            },
+        );
+
+        let objects = import(&export(&state, &HashMap::new())).unwrap();
+        assert_eq!(objects.len(), 2);
+
+        match &objects[0] {
+            ConfObject::VirtualDevice { props } => {
+                assert!(props.contains(&("node.name".to_string(), "virtual_sink".to_string())));
+                assert!(props.contains(&(
+                    "node.description".to_string(),
+                    "Virtual \"sink\"".to_string()
+                )));
+            }
+            object => panic!("Expected a virtual device, got {:?}", object),
+        }
+        match &objects[1] {
+            ConfObject::Link(link) => assert_eq!(
+                link,
+                &ConfLink {
+                    output_node: "player".to_string(),
+                    output_port: "output_FL".to_string(),
+                    input_node: "virtual_sink".to_string(),
+                    input_port: "playback_FL".to_string(),
+                }
+            ),
+            object => panic!("Expected a link, got {:?}", object),
+        }
+    }
+
+    #[test]
+    fn port_labels_can_be_imported() {
+        let port_labels = [
+            ("Firefox:output_FL", "Left"),
+            ("Speakers:playback_FL", "Desk \"left\""),
+        ]
+        .iter()
+        .map(|(path, label)| (path.to_string(), label.to_string()))
+        .collect::<HashMap<_, _>>();
+
+        let mut imported = import(&export(&State::new(), &port_labels))
+            .unwrap()
+            .into_iter()
+            .map(|object| match object {
+                ConfObject::PortLabel { path, label } => (path, label),
+                object => panic!("Expected a port label, got {:?}", object),
+            })
+            .collect::<Vec<_>>();
+        imported.sort();
+
+        let mut expected = port_labels.into_iter().collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(imported, expected);
+    }
+
+    #[test]
+    fn links_to_devices_are_left_to_pw_viz() {
+        let mut state = State::new();
+        state.add(
+            1,
+            node(
+                "Built-in Audio",
+                &[
+                    ("node.name", "alsa_input.pci-0000_00_1f.3.analog-stereo"),
+                    ("media.class", "Audio/Source"),
+                ],
+            ),
+        );
+        state.add(
+            2,
+            node(
+                "OBS",
+                &[("node.name", "obs"), ("media.class", "Stream/Input/Audio")],
+            ),
+        );
+        state.add(3, port(1, 3, "capture_FL"));
+        state.add(4, port(2, 4, "input_FL"));
+        state.add(
+            5,
+            GlobalObject::Link {
+                from_port: 3,
+                to_port: 4,
+            },
+        );
+
+        let expected = header()
+            + "context.objects = [\n]\n"
+            + "pw-viz.links = [\n"
+            + "    {\n"
+            + "        link.output.node = \"alsa_input.pci-0000_00_1f.3.analog-stereo\"\n"
+            + "        link.output.port = \"capture_FL\"\n"
+            + "        link.input.node = \"obs\"\n"
+            + "        link.input.port = \"input_FL\"\n"
+            + "    }\n"
+            + "]\n";
+        assert_eq!(export(&state, &HashMap::new()), expected);
+    }
+
+    #[test]
+    fn loopbacks_are_exported_as_modules() {
+        let mut state = State::new();
+        state.add(
+            1,
+            node(
+                "Virtual sink",
+                &[
+                    ("factory.name", NULL_SINK_FACTORY),
+                    ("node.name", "virtual_sink"),
+                    ("media.class", "Audio/Sink"),
+                ],
+            ),
+        );
+        state.add(
+            2,
+            node(
+                "Loopback sink",
+                &[
+                    ("node.name", "loopback_sink"),
+                    ("media.class", "Audio/Sink"),
+                    ("node.link-group", "loopback-1234-21"),
+                ],
+            ),
+        );
+        state.add(
+            3,
+            node(
+                "Loopback playback",
+                &[
+                    ("node.name", "loopback_playback"),
+                    ("media.class", "Stream/Output/Audio"),
+                    ("node.link-group", "loopback-1234-21"),
+                ],
+            ),
+        );
+        state.add(4, port(3, 4, "output_FL"));
+        state.add(5, port(1, 5, "playback_FL"));
+        state.add(
+            6,
+            GlobalObject::Link {
+                from_port: 4,
+                to_port: 5,
+            },
+        );
+
+        let expected = header()
+            + "context.modules = [\n"
+            + "    { name = libpipewire-module-loopback\n"
+            + "        args = {\n"
+            + "            capture.props = {\n"
+            + "                node.name = \"loopback_sink\"\n"
+            + "                media.class = \"Audio/Sink\"\n"
+            + "            }\n"
+            + "            playback.props = {\n"
+            + "                node.name = \"loopback_playback\"\n"
+            + "                media.class = \"Stream/Output/Audio\"\n"
+            + "            }\n"
+            + "        }\n"
+            + "    }\n"
+            + "]\n"
+            + "context.objects = [\n"
+            + "    { factory = adapter\n"
+            + "        args = {\n"
+            + "            factory.name = \"support.null-audio-sink\"\n"
+            + "            node.name = \"virtual_sink\"\n"
+            + "            media.class = \"Audio/Sink\"\n"
+            + "        }\n"
+            + "    }\n"
+            + "    { factory = link-factory\n"
+            + "        args = {\n"
+            + "            link.output.node = \"loopback_playback\"\n"
+            + "            link.output.port = \"output_FL\"\n"
+            + "            link.input.node = \"virtual_sink\"\n"
+            + "            link.input.port = \"playback_FL\"\n"
+            + "            object.linger = true\n"
+            + "        }\n"
+            + "        flags = [ nofail ]\n"
+            + "    }\n"
+            + "]\n";
+        assert_eq!(export(&state, &HashMap::new()), expected);
+    }
+}
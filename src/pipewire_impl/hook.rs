@@ -0,0 +1,57 @@
+//! Listening to proxies of interfaces which aren't wrapped by pipewire-rs
+
+use std::{any::Any, ffi::c_void, mem};
+
+use libspa_sys as spa_sys;
+use pipewire::proxy::Proxy;
+use pipewire_sys as pw_sys;
+
+/// Keeps a listener registered on a proxy until dropped
+/// This has to be dropped before the proxy it was added to is destroyed
+pub struct ObjectListener {
+    hook: Box<spa_sys::spa_hook>,
+    _events: Box<dyn Any>,
+    _callback: Box<dyn Any>,
+}
+
+impl ObjectListener {
+    /// # Safety
+    /// `events` has to be a `#[repr(C)]` events struct matching the interface of the proxy,
+    /// whose functions expect a pointer to `callback` as their data argument
+    pub unsafe fn add<E: 'static, C: 'static>(proxy: &Proxy, events: E, callback: C) -> Self {
+        let events = Box::new(events);
+        let callback = Box::new(callback);
+        let mut hook: Box<spa_sys::spa_hook> = Box::new(mem::zeroed());
+
+        pw_sys::pw_proxy_add_object_listener(
+            proxy.as_ptr(),
+            &mut *hook,
+            &*events as *const E as *const c_void,
+            &*callback as *const C as *mut c_void,
+        );
+
+        Self {
+            hook,
+            _events: events,
+            _callback: callback,
+        }
+    }
+}
+
+impl Drop for ObjectListener {
+    fn drop(&mut self) {
+        // Equivalent of spa_hook_remove, which is an inline function
+        unsafe {
+            let link = &mut self.hook.link;
+            if !link.prev.is_null() && !link.next.is_null() {
+                (*link.prev).next = link.next;
+                (*link.next).prev = link.prev;
+                link.prev = std::ptr::null_mut();
+                link.next = std::ptr::null_mut();
+            }
+            if let Some(removed) = self.hook.removed {
+                removed(&mut *self.hook);
+            }
+        }
+    }
+}
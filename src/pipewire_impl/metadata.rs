@@ -0,0 +1,154 @@
+//! A minimal proxy for the metadata interface, which isn't wrapped by pipewire-rs
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::raw::c_char,
+    ptr,
+};
+
+use libspa_sys as spa_sys;
+use pipewire::{proxy::Proxy, proxy::ProxyT, types::ObjectType};
+
+use super::hook::ObjectListener;
+
+const PW_VERSION_METADATA_EVENTS: u32 = 0;
+
+/// Matches `struct pw_metadata_events` from `pipewire/extensions/metadata.h`
+#[repr(C)]
+#[allow(dead_code)]
+struct MetadataEvents {
+    version: u32,
+    property: Option<
+        unsafe extern "C" fn(
+            data: *mut c_void,
+            subject: u32,
+            key: *const c_char,
+            type_: *const c_char,
+            value: *const c_char,
+        ) -> i32,
+    >,
+}
+
+/// Matches `struct pw_metadata_methods` from `pipewire/extensions/metadata.h`
+#[repr(C)]
+#[allow(dead_code)]
+struct MetadataMethods {
+    version: u32,
+    add_listener: Option<
+        unsafe extern "C" fn(
+            object: *mut c_void,
+            listener: *mut spa_sys::spa_hook,
+            events: *const MetadataEvents,
+            data: *mut c_void,
+        ) -> i32,
+    >,
+    set_property: Option<
+        unsafe extern "C" fn(
+            object: *mut c_void,
+            subject: u32,
+            key: *const c_char,
+            type_: *const c_char,
+            value: *const c_char,
+        ) -> i32,
+    >,
+    clear: Option<unsafe extern "C" fn(object: *mut c_void) -> i32>,
+}
+
+pub struct Metadata {
+    proxy: Proxy,
+}
+
+impl ProxyT for Metadata {
+    fn type_() -> ObjectType {
+        ObjectType::Metadata
+    }
+
+    fn upcast(self) -> Proxy {
+        self.proxy
+    }
+
+    fn upcast_ref(&self) -> &Proxy {
+        &self.proxy
+    }
+
+    unsafe fn from_proxy_unchecked(proxy: Proxy) -> Self
+    where
+        Self: Sized,
+    {
+        Self { proxy }
+    }
+}
+
+unsafe fn optional_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        None
+    } else {
+        CStr::from_ptr(ptr).to_str().ok()
+    }
+}
+
+impl Metadata {
+    /// Sets a property on `subject`, a value of `None` removes the property
+    pub fn set_property(&self, subject: u32, key: &str, type_: Option<&str>, value: Option<&str>) {
+        let to_c_string = |value: Option<&str>| value.and_then(|value| CString::new(value).ok());
+
+        let key = match CString::new(key) {
+            Ok(key) => key,
+            Err(_) => return,
+        };
+        let type_ = to_c_string(type_);
+        let value = to_c_string(value);
+
+        unsafe {
+            // The interface is the first member of a proxy
+            let iface = self.proxy.as_ptr() as *mut spa_sys::spa_interface;
+            let methods = (*iface).cb.funcs as *const MetadataMethods;
+
+            if let Some(set_property) = methods.as_ref().and_then(|methods| methods.set_property) {
+                let res = set_property(
+                    (*iface).cb.data,
+                    subject,
+                    key.as_ptr(),
+                    type_.as_ref().map_or(ptr::null(), |type_| type_.as_ptr()),
+                    value.as_ref().map_or(ptr::null(), |value| value.as_ptr()),
+                );
+                if res < 0 {
+                    log::error!("Failed to set metadata property: {}", res);
+                }
+            }
+        }
+    }
+
+    /// The callback is called with the subject, key, type and value of every property that changes
+    pub fn add_listener_local<F>(&self, callback: F) -> ObjectListener
+    where
+        F: Fn(u32, &str, Option<&str>, Option<&str>) + 'static,
+    {
+        unsafe extern "C" fn property<F>(
+            data: *mut c_void,
+            subject: u32,
+            key: *const c_char,
+            type_: *const c_char,
+            value: *const c_char,
+        ) -> i32
+        where
+            F: Fn(u32, &str, Option<&str>, Option<&str>),
+        {
+            let callback = &*(data as *const F);
+
+            // A null key means all properties of the subject were removed
+            if let Some(key) = optional_str(key) {
+                callback(subject, key, optional_str(type_), optional_str(value));
+            }
+
+            0
+        }
+
+        let events = MetadataEvents {
+            version: PW_VERSION_METADATA_EVENTS,
+            property: Some(property::<F>),
+        };
+
+        unsafe { ObjectListener::add(&self.proxy, events, callback) }
+    }
+}
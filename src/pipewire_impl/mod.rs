@@ -1,30 +1,65 @@
+mod conf;
+mod hook;
+mod metadata;
+mod oneshot;
+mod params;
+mod profiler;
 mod state;
 
 use pipewire::{
+    client::ClientChangeMask,
     link::LinkChangeMask,
-    prelude::ReadableDict,
+    node::NodeChangeMask,
+    permissions::{Permission, PermissionFlags},
+    port::PortChangeMask,
+    prelude::{ReadableDict, WritableDict},
+    proxy::ProxyT,
     registry::{GlobalObject, Registry},
     spa::ForeignDict,
     Context, Core, MainLoop,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::mpsc::Sender};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::Path,
+    rc::Rc,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
 
 use crate::ui::UiMessage;
+use serde::{Deserialize, Serialize};
 use state::State;
 
+pub use conf::{default_path as default_config_path, xdg_config_dir};
+pub use oneshot::run as run_command;
+pub use profiler::Profile;
+
+/// Profiler data is accumulated and sent to the ui at this interval
+const PROFILE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Set on links created by pw-viz, so that they can be told apart from links made by the session manager
+const CREATED_LINK_KEY: &str = "pw-viz.created";
+
 pub enum PipewireMessage {
     NodeAdded {
         id: u32,
-        name: String,
-        description: Option<String>,
-        media_type: Option<MediaType>,
+        properties: NodeProperties,
     },
     PortAdded {
         node_name: String,
         node_id: u32,
         id: u32,
-        name: String,
-        port_type: PortType,
+        properties: PortProperties,
+    },
+    NodeChanged {
+        id: u32,
+        properties: NodeProperties,
+    },
+    PortChanged {
+        node_id: u32,
+        id: u32,
+        properties: PortProperties,
     },
     LinkAdded {
         id: u32,
@@ -33,11 +68,26 @@ pub enum PipewireMessage {
 
         from_port: u32,
         to_port: u32,
+        properties: LinkProperties,
+    },
+    LinkChanged {
+        id: u32,
+        properties: LinkProperties,
     },
     LinkStateChanged {
         id: u32,
         active: bool,
     },
+    /// A link requested by the ui couldn't be created
+    LinkFailed {
+        from_port: u32,
+        to_port: u32,
+        error: String,
+    },
+    NodeStateChanged {
+        id: u32,
+        state: NodeState,
+    },
     NodeRemoved {
         name: String,
         id: u32,
@@ -50,15 +100,52 @@ pub enum PipewireMessage {
     LinkRemoved {
         id: u32,
     },
+    ClientAdded {
+        id: u32,
+        properties: ClientProperties,
+    },
+    ClientChanged {
+        id: u32,
+        properties: ClientProperties,
+    },
+    /// Permissions a client has on other objects, starting at `index` of its permission list
+    ClientPermissions {
+        id: u32,
+        index: u32,
+        permissions: Vec<(u32, PermissionFlags)>,
+    },
+    ClientRemoved {
+        id: u32,
+    },
+    /// Timings of the drivers and nodes which ran since the last update
+    Profile(Profile),
+    /// A `clock.*` property of the settings metadata changed, `None` if it was removed
+    SettingChanged {
+        key: String,
+        value: Option<String>,
+    },
+    /// Port labels read from an imported config, keyed by `<node>:<port>` paths
+    PortLabelsImported(Vec<(String, String)>),
+    /// Something requested by the ui failed, the error is shown to the user
+    Error(String),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum MediaType {
     Audio,
     Video,
     Midi,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeState {
+    Creating,
+    Suspended,
+    Idle,
+    Running,
+    Error(String),
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum PortType {
     Input,
@@ -66,7 +153,147 @@ pub enum PortType {
     Unknown,
 }
 
-type Proxies = HashMap<u32, ProxyLink>;
+/// What is needed to tell whether two ports can be linked
+#[derive(Debug, Clone, Copy)]
+pub struct LinkEndpoint<'a> {
+    pub port_type: PortType,
+    pub media_type: Option<MediaType>,
+    pub format: Option<&'a str>,
+}
+
+impl<'a> LinkEndpoint<'a> {
+    /// Checks whether the two ports can be linked, in either direction, returns the reason if they can't
+    pub fn check(&self, other: &LinkEndpoint) -> Result<(), String> {
+        match (self.port_type, other.port_type) {
+            (PortType::Input, PortType::Output) | (PortType::Output, PortType::Input) => {}
+            (PortType::Input, PortType::Input) => {
+                return Err("An input can't be linked to another input".to_string())
+            }
+            (PortType::Output, PortType::Output) => {
+                return Err("An output can't be linked to another output".to_string())
+            }
+            _ => return Err("The direction of the port is unknown".to_string()),
+        }
+
+        if let (Some(a), Some(b)) = (self.media_type, other.media_type) {
+            if a != b {
+                return Err(format!("{:?} can't be linked to {:?}", a, b));
+            }
+        }
+
+        if let (Some(a), Some(b)) = (self.format, other.format) {
+            if a != b {
+                return Err(format!("Incompatible formats: {} and {}", a, b));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Properties of a node which are shown in the ui
+#[derive(Debug, Clone)]
+pub struct NodeProperties {
+    /// Nodes with the same name are shown as one ui node, the name is also what node settings are saved under
+    pub name: String,
+    pub description: Option<String>,
+    pub media_type: Option<MediaType>,
+    /// Title of what a stream is currently playing, `media.name`
+    pub media_name: Option<String>,
+    pub icon_name: Option<String>,
+    /// Id of the client which owns the node, `client.id`
+    pub client_id: Option<u32>,
+    /// Id of the driver the node is scheduled by, `node.driver-id`
+    pub driver_id: Option<u32>,
+}
+
+/// Properties of a client which are shown in the ui
+#[derive(Debug, Clone)]
+pub struct ClientProperties {
+    pub name: Option<String>,
+    pub pid: Option<u32>,
+    /// Path of the executable of the client process, `application.process.binary`
+    pub binary: Option<String>,
+    /// How the client was granted access, e.g. `unrestricted` or `flatpak`, `pipewire.access`
+    pub access: Option<String>,
+}
+
+/// Properties pw-viz sets when it creates a link
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkOptions {
+    /// The link outlives the client which created it, `object.linger`
+    pub linger: bool,
+    /// The link doesn't keep its nodes running, `link.passive`
+    pub passive: bool,
+    /// The link closes a loop in the graph, `link.feedback`
+    pub feedback: bool,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        Self {
+            linger: true,
+            passive: false,
+            feedback: false,
+        }
+    }
+}
+
+/// Properties of a link which are shown in the ui
+#[derive(Debug, Clone, Default)]
+pub struct LinkProperties {
+    /// The link was created by pw-viz
+    pub created: bool,
+    pub linger: bool,
+    pub passive: bool,
+    pub feedback: bool,
+    /// Id of the factory which created the link, `factory.id`
+    pub factory_id: Option<u32>,
+    /// Every property of the link
+    pub props: HashMap<String, String>,
+}
+
+impl LinkProperties {
+    /// Options to recreate the link with
+    pub fn options(&self) -> LinkOptions {
+        LinkOptions {
+            linger: self.linger,
+            passive: self.passive,
+            feedback: self.feedback,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PortProperties {
+    pub name: String,
+    pub alias: Option<String>,
+    /// Channel position of the port, `audio.channel`
+    pub channel: Option<String>,
+    /// Index of the port in its node, `port.id`
+    pub index: Option<u32>,
+    pub monitor: bool,
+    pub port_type: PortType,
+    /// Sample format of DSP ports, `format.dsp`, e.g. `32 bit float mono audio`
+    pub format: Option<String>,
+}
+
+type Proxies = HashMap<u32, ProxyObject>;
+
+#[allow(dead_code)]
+enum ProxyObject {
+    Client(ProxyClient),
+    Link(ProxyLink),
+    Node(ProxyNode),
+    Port(ProxyPort),
+}
+
+#[allow(dead_code)]
+struct ProxyClient {
+    proxy: pipewire::client::Client,
+    listener: pipewire::client::ClientListener,
+}
 
 #[allow(dead_code)]
 struct ProxyLink {
@@ -74,6 +301,52 @@ struct ProxyLink {
     listener: pipewire::link::LinkListener,
 }
 
+/// A link created by pw-viz, kept until pipewire reports whether it could be set up.
+/// Links which don't linger are destroyed along with their proxy, so they are kept until they are removed
+#[allow(dead_code)]
+struct PendingLink {
+    proxy: pipewire::link::Link,
+    listener: pipewire::link::LinkListener,
+    from_port: u32,
+    to_port: u32,
+    linger: bool,
+    finished: Rc<Cell<bool>>,
+    /// Id of the global once the link has been set up
+    global_id: Rc<Cell<Option<u32>>>,
+}
+
+type PendingLinks = HashMap<u32, PendingLink>;
+
+/// Links of an imported config which are made as soon as both of their ports appear,
+/// virtual devices created by the same config only get their ports after a while
+type ImportedLinks = Vec<conf::ConfLink>;
+
+#[allow(dead_code)]
+struct ProxyNode {
+    proxy: pipewire::node::Node,
+    listener: pipewire::node::NodeListener,
+}
+
+#[allow(dead_code)]
+struct ProxyPort {
+    proxy: pipewire::port::Port,
+    listener: pipewire::port::PortListener,
+}
+
+#[allow(dead_code)]
+struct ProxyMetadata {
+    // The listener has to be removed before the proxy is destroyed
+    listener: hook::ObjectListener,
+    proxy: metadata::Metadata,
+}
+
+#[allow(dead_code)]
+struct ProxyProfiler {
+    // The listener has to be removed before the proxy is destroyed
+    listener: hook::ObjectListener,
+    proxy: profiler::Profiler,
+}
+
 /// Pipewire mainloop runs on a separate thread, and notifies the UI thread of any changes using a mpsc channel
 /// thread_main is the entry point of this thread
 pub fn thread_main(
@@ -86,29 +359,75 @@ pub fn thread_main(
 
     let proxies = Rc::new(RefCell::new(Default::default()));
     let proxies_rm = proxies.clone();
+    let proxies_ui = proxies.clone();
 
     let registry = Rc::new(core.get_registry()?);
     let registry_clone = registry.clone();
 
     let sender_rm = sender.clone();
+    let sender_ui = sender.clone();
 
     let state = Rc::new(RefCell::new(State::new()));
     let state_rm = state.clone();
     let state_rm_link = state.clone();
 
+    let profiler = Rc::new(RefCell::new(None));
+    let settings = Rc::new(RefCell::new(None));
+    let settings_ui = settings.clone();
+
+    let pending_links = Rc::new(RefCell::new(PendingLinks::new()));
+    let pending_links_rm = pending_links.clone();
+
+    let imported_links = Rc::new(RefCell::new(ImportedLinks::new()));
+
+    // Errors of objects created by pw-viz, like links that couldn't be made, are reported on the core
+    let _core_listener = core
+        .add_listener_local()
+        .error({
+            let sender = sender.clone();
+            let pending_links = pending_links.clone();
+
+            move |id, _seq, _res, message| {
+                // The link won't be set up anymore, so it doesn't have to be tracked
+                let link = pending_links.borrow_mut().remove(&id);
+                if let Some(link) = link {
+                    fail_link(&link, message, &sender);
+                } else {
+                    log::error!("Pipewire error on object {}: {}", id, message);
+                }
+            }
+        })
+        .register();
+
     let _listener = registry
         .add_listener_local()
         // Called when a global object is added
         .global({
+            let core = core.clone();
+            let imported_links = imported_links.clone();
+            let pending_links = pending_links.clone();
+
             move |global| match global.type_ {
                 pipewire::types::ObjectType::Node => {
-                    handle_node(global, &state, &sender);
+                    handle_node(global, &state, &sender, &registry_clone, &proxies);
                 }
                 pipewire::types::ObjectType::Link => {
                     handle_link(global, &state, &sender, &registry_clone, &proxies);
                 }
                 pipewire::types::ObjectType::Port => {
-                    handle_port(global, &state, &sender);
+                    handle_port(global, &state, &sender, &registry_clone, &proxies);
+                    if !imported_links.borrow().is_empty() {
+                        add_imported_links(&state, &core, &imported_links, &sender, &pending_links);
+                    }
+                }
+                pipewire::types::ObjectType::Client => {
+                    handle_client(global, &state, &sender, &registry_clone, &proxies);
+                }
+                pipewire::types::ObjectType::Profiler => {
+                    handle_profiler(global, &sender, &registry_clone, &profiler);
+                }
+                pipewire::types::ObjectType::Metadata => {
+                    handle_metadata(global, &sender, &registry_clone, &settings);
                 }
                 _ => {}
             }
@@ -116,18 +435,24 @@ pub fn thread_main(
         // Called when a global object is removed
         .global_remove(move |id| match state_rm.borrow_mut().remove(id) {
             Some(object) => {
+                prune_pending_links(&pending_links_rm, Some(id));
+
                 let message = match object {
-                    state::GlobalObject::Node { name } => PipewireMessage::NodeRemoved { name, id },
-                    state::GlobalObject::Link => PipewireMessage::LinkRemoved { id },
+                    state::GlobalObject::Node { name, .. } => {
+                        PipewireMessage::NodeRemoved { name, id }
+                    }
+                    state::GlobalObject::Link { .. } => PipewireMessage::LinkRemoved { id },
                     state::GlobalObject::Port {
                         node_name,
                         node_id,
                         id,
+                        ..
                     } => PipewireMessage::PortRemoved {
                         node_name,
                         node_id,
                         id,
                     },
+                    state::GlobalObject::Client => PipewireMessage::ClientRemoved { id },
                 };
                 sender_rm
                     .send(message)
@@ -151,9 +476,53 @@ pub fn thread_main(
             UiMessage::RemoveLink(link_id) => {
                 remove_link(link_id, &state, &registry);
             }
-            UiMessage::AddLink { from_port, to_port } => {
-                add_link(&state, from_port, to_port, &core)
+            UiMessage::AddLink {
+                from_port,
+                to_port,
+                options,
+            } => match add_link(&state, from_port, to_port, options, &core) {
+                Some(proxy) => track_link(
+                    proxy,
+                    from_port,
+                    to_port,
+                    options.linger,
+                    &sender_ui,
+                    &pending_links,
+                ),
+                None => sender_ui
+                    .send(PipewireMessage::LinkFailed {
+                        from_port,
+                        to_port,
+                        error: "Couldn't create the link".to_string(),
+                    })
+                    .expect("Failed to send pipewire message"),
+            },
+            UiMessage::ExportConfig { path, port_labels } => {
+                export_config(&path, &state, &port_labels)
+            }
+            UiMessage::ImportConfig(path) => import_config(
+                &path,
+                &state,
+                &core,
+                &imported_links,
+                &sender_ui,
+                &pending_links,
+            ),
+            UiMessage::ForceQuantum(quantum) => {
+                set_setting(&settings_ui, "clock.force-quantum", quantum)
             }
+            UiMessage::ForceRate(rate) => set_setting(&settings_ui, "clock.force-rate", rate),
+            UiMessage::GetClientPermissions(client_id) => {
+                get_client_permissions(client_id, &proxies_ui)
+            }
+            UiMessage::UpdateClientPermissions {
+                client_id,
+                permissions,
+            } => update_client_permissions(client_id, permissions, &proxies_ui),
+            UiMessage::SetVolume { node_id, volume } => match proxies_ui.borrow().get(&node_id) {
+                Some(ProxyObject::Node(node)) => params::set_volume(&node.proxy, volume),
+                _ => log::warn!("Node with id: {} was never registered", node_id),
+            },
             UiMessage::Exit => mainloop.quit(),
         }
     });
@@ -163,50 +532,231 @@ pub fn thread_main(
     Ok(())
 }
 
+fn collect_props<'a>(props: impl Iterator<Item = (&'a str, &'a str)>) -> HashMap<String, String> {
+    props
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+pub fn node_properties(props: &HashMap<String, String>) -> NodeProperties {
+    let props_get = |key: &str| props.get(key).map(String::as_str);
+
+    let description = props_get("node.description");
+    let media_class = props_get("media.class").unwrap_or_default();
+    let is_stream = media_class.starts_with("Stream/");
+
+    // Streams are named after the application that owns them, so that they keep their name across restarts,
+    // several streams of one application are shown as one node
+    let application_name = if is_stream {
+        props_get("application.name")
+    } else {
+        None
+    };
+
+    let name = application_name
+        .or_else(|| props_get("node.nick"))
+        .or(description)
+        .or_else(|| props_get("node.name"))
+        .unwrap_or_default()
+        .to_string();
+
+    let media_type = if media_class.contains("Audio") {
+        Some(MediaType::Audio)
+    } else if media_class.contains("Video") {
+        Some(MediaType::Video)
+    } else if media_class.contains("Midi") {
+        Some(MediaType::Midi)
+    } else {
+        None
+    };
+
+    let media_name = if is_stream {
+        props_get("media.name").map(|media_name| media_name.to_string())
+    } else {
+        None
+    };
+
+    let icon_name = props_get("application.icon-name")
+        .or_else(|| props_get("media.icon-name"))
+        .map(|icon_name| icon_name.to_string());
+
+    let client_id = props_get("client.id").and_then(|client_id| client_id.parse().ok());
+    let driver_id = props_get("node.driver-id").and_then(|driver_id| driver_id.parse().ok());
+
+    NodeProperties {
+        name,
+        description: description.map(|desc| desc.to_string()),
+        media_type,
+        media_name,
+        icon_name,
+        client_id,
+        driver_id,
+    }
+}
+
+fn client_properties(props: &HashMap<String, String>) -> ClientProperties {
+    let props_get = |key: &str| props.get(key).map(String::as_str);
+
+    ClientProperties {
+        name: props_get("application.name").map(|name| name.to_string()),
+        pid: props_get("application.process.id")
+            .or_else(|| props_get("pipewire.sec.pid"))
+            .and_then(|pid| pid.parse().ok()),
+        binary: props_get("application.process.binary").map(|binary| binary.to_string()),
+        access: props_get("pipewire.access").map(|access| access.to_string()),
+    }
+}
+
+fn handle_client(
+    client: &GlobalObject<ForeignDict>,
+    state: &Rc<RefCell<State>>,
+    sender: &Rc<Sender<PipewireMessage>>,
+    registry: &Rc<Registry>,
+    proxies: &Rc<RefCell<Proxies>>,
+) {
+    let properties = client_properties(
+        &client
+            .props
+            .as_ref()
+            .map(|props| collect_props(props.iter()))
+            .unwrap_or_default(),
+    );
+
+    state
+        .borrow_mut()
+        .add(client.id, state::GlobalObject::Client);
+
+    sender
+        .send(PipewireMessage::ClientAdded {
+            id: client.id,
+            properties,
+        })
+        .expect("Failed to send pipewire message");
+
+    // The client proxy is bound to receive property updates, which also carry the full set of properties
+    let proxy: pipewire::client::Client =
+        registry.bind(client).expect("Failed to bind client proxy");
+
+    let id = client.id;
+    let sender_permissions = sender.clone();
+    let sender = sender.clone();
+
+    let listener = proxy
+        .add_listener_local()
+        .info(move |info| {
+            if info.change_mask().contains(ClientChangeMask::PROPS) {
+                if let Some(props) = info.props() {
+                    let properties = client_properties(&collect_props(props.iter()));
+
+                    sender
+                        .send(PipewireMessage::ClientChanged {
+                            id: info.id(),
+                            properties,
+                        })
+                        .expect("Failed to send pipewire message");
+                }
+            }
+        })
+        // Only sent in response to get_permissions
+        .permissions(move |index, permissions| {
+            let permissions = permissions
+                .iter()
+                .map(|permission| (permission.id(), permission.permission_flags()))
+                .collect();
+
+            sender_permissions
+                .send(PipewireMessage::ClientPermissions {
+                    id,
+                    index,
+                    permissions,
+                })
+                .expect("Failed to send pipewire message");
+        })
+        .register();
+
+    proxies.borrow_mut().insert(
+        client.id,
+        ProxyObject::Client(ProxyClient { proxy, listener }),
+    );
+}
+
 fn handle_node(
     node: &GlobalObject<ForeignDict>,
     state: &Rc<RefCell<State>>,
     sender: &Rc<Sender<PipewireMessage>>,
+    registry: &Rc<Registry>,
+    proxies: &Rc<RefCell<Proxies>>,
 ) {
     let props = node
         .props
         .as_ref()
         .expect("Node object doesn't have properties");
 
-    let description = props.get("node.description");
-
-    let name = props
-        .get("node.nick")
-        .or(description)
-        .or_else(|| props.get("node.name"))
-        .unwrap_or_default()
-        .to_string();
-
-    let media_type = props.get("media.class").and_then(|class| {
-        if class.contains("Audio") {
-            Some(MediaType::Audio)
-        } else if class.contains("Video") {
-            Some(MediaType::Video)
-        } else if class.contains("Midi") {
-            Some(MediaType::Midi)
-        } else {
-            None
-        }
-    });
+    let node_props = collect_props(props.iter());
+    let properties = node_properties(&node_props);
 
-    state
-        .borrow_mut()
-        .add(node.id, state::GlobalObject::Node { name: name.clone() });
+    state.borrow_mut().add(
+        node.id,
+        state::GlobalObject::Node {
+            name: properties.name.clone(),
+            props: node_props,
+        },
+    );
 
-    let description = description.map(|desc| desc.to_string());
     sender
         .send(PipewireMessage::NodeAdded {
             id: node.id,
-            name,
-            description,
-            media_type,
+            properties,
         })
         .expect("Failed to send pipewire message");
+
+    // The node proxy is bound to receive state and property updates
+    let proxy: pipewire::node::Node = registry.bind(node).expect("Failed to bind node proxy");
+
+    let sender = sender.clone();
+    let state = state.clone();
+
+    let listener = proxy
+        .add_listener_local()
+        .info(move |info| {
+            let id = info.id();
+
+            if info.change_mask().contains(NodeChangeMask::PROPS) {
+                if let Some(props) = info.props() {
+                    let node_props = collect_props(props.iter());
+                    let properties = node_properties(&node_props);
+
+                    let changed = state
+                        .borrow_mut()
+                        .update_node(id, &properties.name, node_props);
+
+                    if changed {
+                        sender
+                            .send(PipewireMessage::NodeChanged { id, properties })
+                            .expect("Failed to send pipewire message");
+                    }
+                }
+            }
+
+            if info.change_mask().contains(NodeChangeMask::STATE) {
+                let state = match info.state() {
+                    pipewire::node::NodeState::Creating => NodeState::Creating,
+                    pipewire::node::NodeState::Suspended => NodeState::Suspended,
+                    pipewire::node::NodeState::Idle => NodeState::Idle,
+                    pipewire::node::NodeState::Running => NodeState::Running,
+                    pipewire::node::NodeState::Error(err) => NodeState::Error(err.to_string()),
+                };
+
+                sender
+                    .send(PipewireMessage::NodeStateChanged { id, state })
+                    .expect("Failed to send pipewire message");
+            }
+        })
+        .register();
+
+    proxies
+        .borrow_mut()
+        .insert(node.id, ProxyObject::Node(ProxyNode { proxy, listener }));
 }
 
 fn handle_link(
@@ -226,6 +776,8 @@ fn handle_link(
         .info(move |info| {
             let id = info.id();
 
+            let properties = info.props().map(link_properties).unwrap_or_default();
+
             let from_node = info.output_node_id();
             let from_port = info.output_port_id();
             let to_node = info.input_node_id();
@@ -234,22 +786,27 @@ fn handle_link(
             let mut state = state.borrow_mut();
 
             let from_node_name = match state.get(from_node).expect("Id wasn't registered") {
-                state::GlobalObject::Node { name } => name.clone(),
+                state::GlobalObject::Node { name, .. } => name.clone(),
                 _ => unreachable!(),
             };
             let to_node_name = match state.get(to_node).expect("Id wasn't registered") {
-                state::GlobalObject::Node { name } => name.clone(),
+                state::GlobalObject::Node { name, .. } => name.clone(),
                 _ => unreachable!(),
             };
 
-            if let Some(&state::GlobalObject::Link) = state.get(id) {
+            if let Some(&state::GlobalObject::Link { .. }) = state.get(id) {
                 if info.change_mask().contains(LinkChangeMask::STATE) {
                     sender
                         .send(PipewireMessage::LinkStateChanged { id, active: true })
                         .expect("Failed to send pipewire message");
                 }
+                if info.change_mask().contains(LinkChangeMask::PROPS) {
+                    sender
+                        .send(PipewireMessage::LinkChanged { id, properties })
+                        .expect("Failed to send pipewire message");
+                }
             } else {
-                state.add(id, state::GlobalObject::Link);
+                state.add(id, state::GlobalObject::Link { from_port, to_port });
                 log::debug!("New pipewire link was added : {}", id);
                 sender
                     .send(PipewireMessage::LinkAdded {
@@ -258,6 +815,7 @@ fn handle_link(
                         from_port,
                         to_port,
                         id,
+                        properties,
                     })
                     .expect("Failed to send pipewire message");
             }
@@ -266,49 +824,262 @@ fn handle_link(
 
     proxies
         .borrow_mut()
-        .insert(link.id, ProxyLink { proxy, listener });
+        .insert(link.id, ProxyObject::Link(ProxyLink { proxy, listener }));
 }
-fn add_link(state: &Rc<RefCell<State>>, from_port: u32, to_port: u32, core: &Rc<Core>) {
-    let state = state.borrow();
-    let from_port_ob = state
-        .get(from_port)
-        .expect(&format!("Port with id {} was never registered", from_port));
-    let from_node = *match from_port_ob {
-        state::GlobalObject::Port {
-            node_name: _,
-            node_id,
-            id: _,
-        } => node_id,
-        _ => unreachable!(),
+fn handle_profiler(
+    global: &GlobalObject<ForeignDict>,
+    sender: &Rc<Sender<PipewireMessage>>,
+    registry: &Rc<Registry>,
+    profiler: &Rc<RefCell<Option<ProxyProfiler>>>,
+) {
+    // Binding the profiler is what enables profiling in the module
+    let proxy: profiler::Profiler = match registry.bind(global) {
+        Ok(proxy) => proxy,
+        Err(err) => {
+            log::error!("Failed to bind profiler: {}", err);
+            return;
+        }
     };
 
-    let to_port_ob = state
-        .get(to_port)
-        .expect(&format!("Port with id {} was never registered", to_port));
-    let to_node = *match to_port_ob {
-        state::GlobalObject::Port {
-            node_name: _,
-            node_id,
-            id: _,
-        } => node_id,
-        _ => unreachable!(),
+    let sender = sender.clone();
+    let pending = RefCell::new(Profile::default());
+    let last_sent = Cell::new(Instant::now());
+
+    let listener = proxy.add_listener_local(move |profile| {
+        let mut pending = pending.borrow_mut();
+        pending.drivers.extend(profile.drivers);
+        pending.nodes.extend(profile.nodes);
+
+        if last_sent.get().elapsed() >= PROFILE_INTERVAL {
+            last_sent.set(Instant::now());
+            sender
+                .send(PipewireMessage::Profile(std::mem::take(&mut *pending)))
+                .expect("Failed to send pipewire message");
+        }
+    });
+
+    *profiler.borrow_mut() = Some(ProxyProfiler { listener, proxy });
+}
+
+fn handle_metadata(
+    global: &GlobalObject<ForeignDict>,
+    sender: &Rc<Sender<PipewireMessage>>,
+    registry: &Rc<Registry>,
+    settings: &Rc<RefCell<Option<ProxyMetadata>>>,
+) {
+    let name = global
+        .props
+        .as_ref()
+        .and_then(|props| props.get("metadata.name"));
+
+    // Only the settings metadata is used, which holds the clock settings of the graph
+    if name != Some("settings") {
+        return;
+    }
+
+    let proxy: metadata::Metadata = match registry.bind(global) {
+        Ok(proxy) => proxy,
+        Err(err) => {
+            log::error!("Failed to bind settings metadata: {}", err);
+            return;
+        }
     };
 
-    core.create_object::<pipewire::link::Link, _>(
+    let sender = sender.clone();
+    let listener = proxy.add_listener_local(move |subject, key, _type, value| {
+        if subject == 0 && key.starts_with("clock.") {
+            sender
+                .send(PipewireMessage::SettingChanged {
+                    key: key.to_string(),
+                    value: value.map(|value| value.to_string()),
+                })
+                .expect("Failed to send pipewire message");
+        }
+    });
+
+    *settings.borrow_mut() = Some(ProxyMetadata { listener, proxy });
+}
+
+/// Sets a clock setting, a value of 0 stops forcing it
+fn set_setting(settings: &Rc<RefCell<Option<ProxyMetadata>>>, key: &str, value: u32) {
+    match settings.borrow().as_ref() {
+        Some(settings) => {
+            let value = value.to_string();
+            settings
+                .proxy
+                .set_property(0, key, Some("Spa:Int"), Some(&value));
+        }
+        None => log::warn!("Settings metadata is not available"),
+    }
+}
+
+fn get_client_permissions(client_id: u32, proxies: &Rc<RefCell<Proxies>>) {
+    match proxies.borrow().get(&client_id) {
+        Some(ProxyObject::Client(client)) => client.proxy.get_permissions(0, u32::MAX),
+        _ => log::warn!("Client with id: {} was never registered", client_id),
+    }
+}
+
+fn update_client_permissions(
+    client_id: u32,
+    permissions: Vec<(u32, PermissionFlags)>,
+    proxies: &Rc<RefCell<Proxies>>,
+) {
+    let permissions = permissions
+        .into_iter()
+        .map(|(id, flags)| Permission::new(id, flags))
+        .collect::<Vec<_>>();
+
+    match proxies.borrow().get(&client_id) {
+        Some(ProxyObject::Client(client)) => {
+            client.proxy.update_permissions(&permissions);
+            // Permissions aren't sent on change, so they have to be queried again
+            client.proxy.get_permissions(0, u32::MAX);
+        }
+        _ => log::warn!("Client with id: {} was never registered", client_id),
+    }
+}
+
+fn link_properties(props: &ForeignDict) -> LinkProperties {
+    let is_set = |key: &str| matches!(props.get(key), Some("true") | Some("1"));
+
+    LinkProperties {
+        created: is_set(CREATED_LINK_KEY),
+        linger: is_set("object.linger"),
+        passive: is_set("link.passive"),
+        feedback: is_set("link.feedback"),
+        factory_id: props.get("factory.id").and_then(|id| id.parse().ok()),
+        props: collect_props(props.iter()),
+    }
+}
+
+/// Creates a link between two ports, if `options.linger` isn't set the link is destroyed once its proxy is dropped
+fn add_link(
+    state: &Rc<RefCell<State>>,
+    from_port: u32,
+    to_port: u32,
+    options: LinkOptions,
+    core: &Rc<Core>,
+) -> Option<pipewire::link::Link> {
+    let state = state.borrow();
+    let node_id = |port_id| match state.get(port_id) {
+        Some(state::GlobalObject::Port { node_id, .. }) => Some(*node_id),
+        _ => {
+            log::warn!("Port with id {} was never registered", port_id);
+            None
+        }
+    };
+
+    // The port may have been removed while the link was being made
+    let (from_node, to_node) = node_id(from_port).zip(node_id(to_port))?;
+
+    match core.create_object::<pipewire::link::Link, _>(
         "link-factory",
         &pipewire::properties! {
             "link.input.port" => to_port.to_string(),
             "link.output.port" => from_port.to_string(),
             "link.input.node" => to_node.to_string(),
             "link.output.node"=> from_node.to_string(),
-            "object.linger" => "1"
+            "object.linger" => options.linger.to_string(),
+            "link.passive" => options.passive.to_string(),
+            "link.feedback" => options.feedback.to_string(),
+            CREATED_LINK_KEY => "true"
+        },
+    ) {
+        Ok(proxy) => Some(proxy),
+        Err(err) => {
+            log::error!("Failed to add new link: {}", err);
+            None
+        }
+    }
+}
+
+fn fail_link(link: &PendingLink, error: &str, sender: &Sender<PipewireMessage>) {
+    if link.finished.replace(true) {
+        return;
+    }
+
+    sender
+        .send(PipewireMessage::LinkFailed {
+            from_port: link.from_port,
+            to_port: link.to_port,
+            error: error.to_string(),
+        })
+        .expect("Failed to send pipewire message");
+}
+
+/// Stops tracking links that have been set up or have failed, unless they are owned by pw-viz and still exist.
+/// If `removed` is the id of a global that was removed, links of that global or its ports are dropped as well
+fn prune_pending_links(pending_links: &RefCell<PendingLinks>, removed: Option<u32>) {
+    pending_links.borrow_mut().retain(|_, link| {
+        let tracked = !link.finished.get() || (!link.linger && link.global_id.get().is_some());
+        let gone = removed.map_or(false, |id| {
+            link.global_id.get() == Some(id) || link.from_port == id || link.to_port == id
+        });
+
+        tracked && !gone
+    });
+}
+
+/// Listens to the state of a link created by pw-viz, to report it if it fails
+fn track_link(
+    proxy: pipewire::link::Link,
+    from_port: u32,
+    to_port: u32,
+    linger: bool,
+    sender: &Rc<Sender<PipewireMessage>>,
+    pending_links: &Rc<RefCell<PendingLinks>>,
+) {
+    prune_pending_links(pending_links, None);
+
+    let finished = Rc::new(Cell::new(false));
+    let global_id = Rc::new(Cell::new(None));
+
+    let listener = proxy
+        .add_listener_local()
+        .info({
+            let sender = sender.clone();
+            let finished = finished.clone();
+            let global_id = global_id.clone();
+
+            move |info| match info.state() {
+                pipewire::link::LinkState::Error(error) => {
+                    if !finished.replace(true) {
+                        sender
+                            .send(PipewireMessage::LinkFailed {
+                                from_port,
+                                to_port,
+                                error: error.to_string(),
+                            })
+                            .expect("Failed to send pipewire message");
+                    }
+                }
+                pipewire::link::LinkState::Paused | pipewire::link::LinkState::Active => {
+                    global_id.set(Some(info.id()));
+                    finished.set(true);
+                }
+                _ => {}
+            }
+        })
+        .register();
+
+    let id = proxy.upcast_ref().id();
+    pending_links.borrow_mut().insert(
+        id,
+        PendingLink {
+            proxy,
+            listener,
+            from_port,
+            to_port,
+            linger,
+            finished,
+            global_id,
         },
-    )
-    .expect("Failed to add new link");
+    );
 }
 
 fn remove_link(link_id: u32, state: &Rc<RefCell<State>>, registry: &Rc<Registry>) {
-    if let Some(&state::GlobalObject::Link) = state.borrow_mut().get(link_id) {
+    if let Some(&state::GlobalObject::Link { .. }) = state.borrow_mut().get(link_id) {
         if let Err(err) = registry.destroy_global(link_id).into_result() {
             log::error!("SPA error: {}", err)
         }
@@ -317,17 +1088,195 @@ fn remove_link(link_id: u32, state: &Rc<RefCell<State>>, registry: &Rc<Registry>
     }
 }
 
+fn export_config(path: &Path, state: &Rc<RefCell<State>>, port_labels: &HashMap<String, String>) {
+    let fragment = conf::export(&state.borrow(), port_labels);
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(path, fragment));
+
+    match result {
+        Ok(_) => log::info!("Exported config to {}", path.display()),
+        Err(err) => log::error!("Failed to export config to {}: {}", path.display(), err),
+    }
+}
+
+fn import_config(
+    path: &Path,
+    state: &Rc<RefCell<State>>,
+    core: &Rc<Core>,
+    imported_links: &Rc<RefCell<ImportedLinks>>,
+    sender: &Rc<Sender<PipewireMessage>>,
+    pending_links: &Rc<RefCell<PendingLinks>>,
+) {
+    let send_error = |error: String| {
+        sender
+            .send(PipewireMessage::Error(error))
+            .expect("Failed to send pipewire message")
+    };
+
+    let objects = std::fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|source| conf::import(&source));
+
+    let objects = match objects {
+        Ok(objects) => objects,
+        Err(err) => {
+            send_error(format!(
+                "Failed to import config from {}: {}",
+                path.display(),
+                err
+            ));
+            return;
+        }
+    };
+
+    let mut links = ImportedLinks::new();
+    let mut port_labels = Vec::new();
+    for object in objects {
+        match object {
+            conf::ConfObject::VirtualDevice { props } => {
+                // Importing the same config twice shouldn't create the devices twice
+                let node_name = props
+                    .iter()
+                    .find(|(key, _)| key == "node.name")
+                    .map(|(_, value)| value.as_str());
+                if let Some(node_name) = node_name {
+                    if state.borrow().find_node(node_name).is_some() {
+                        log::info!("Virtual device {} already exists", node_name);
+                        continue;
+                    }
+                }
+
+                let mut properties = pipewire::properties! {
+                    "object.linger" => "1"
+                };
+                for (key, value) in props {
+                    properties.insert(key, value);
+                }
+
+                if let Err(err) =
+                    core.create_object::<pipewire::node::Node, _>("adapter", &properties)
+                {
+                    send_error(format!("Failed to create virtual device: {}", err));
+                }
+            }
+            conf::ConfObject::Link(link) => links.push(link),
+            conf::ConfObject::PortLabel { path, label } => port_labels.push((path, label)),
+        }
+    }
+
+    if !port_labels.is_empty() {
+        sender
+            .send(PipewireMessage::PortLabelsImported(port_labels))
+            .expect("Failed to send pipewire message");
+    }
+
+    // Links left over from a previous import are replaced
+    *imported_links.borrow_mut() = links;
+    add_imported_links(state, core, imported_links, sender, pending_links);
+
+    let waiting = imported_links.borrow().len();
+    if waiting > 0 {
+        log::info!("{} imported links are waiting for their ports", waiting);
+    }
+}
+
+/// Makes the imported links whose ports exist, the others are kept until their ports appear
+fn add_imported_links(
+    state: &Rc<RefCell<State>>,
+    core: &Rc<Core>,
+    imported_links: &Rc<RefCell<ImportedLinks>>,
+    sender: &Rc<Sender<PipewireMessage>>,
+    pending_links: &Rc<RefCell<PendingLinks>>,
+) {
+    let ready = {
+        let state = state.borrow();
+        let find_port = |node_name: &str, port_name: &str| {
+            state
+                .find_node(node_name)
+                .and_then(|node_id| state.find_port(node_id, port_name))
+        };
+
+        let mut ready = Vec::new();
+        imported_links.borrow_mut().retain(|link| {
+            let ports = find_port(&link.output_node, &link.output_port)
+                .zip(find_port(&link.input_node, &link.input_port));
+
+            match ports {
+                Some(ports) => {
+                    ready.push((link.clone(), ports));
+                    false
+                }
+                None => true,
+            }
+        });
+        ready
+    };
+
+    for (link, (from_port, to_port)) in ready {
+        if state.borrow().find_link(from_port, to_port).is_some() {
+            log::debug!("Imported link {} already exists", link);
+            continue;
+        }
+
+        log::info!("Linking {}", link);
+        let options = LinkOptions::default();
+        match add_link(state, from_port, to_port, options, core) {
+            Some(proxy) => track_link(
+                proxy,
+                from_port,
+                to_port,
+                options.linger,
+                sender,
+                pending_links,
+            ),
+            None => sender
+                .send(PipewireMessage::LinkFailed {
+                    from_port,
+                    to_port,
+                    error: format!("Couldn't create imported link {}", link),
+                })
+                .expect("Failed to send pipewire message"),
+        }
+    }
+}
+
+fn port_properties(props: &HashMap<String, String>) -> PortProperties {
+    let props_get = |key: &str| props.get(key).map(String::as_str);
+
+    let port_type = match props_get("port.direction") {
+        Some("in") => PortType::Input,
+        Some("out") => PortType::Output,
+        _ => PortType::Unknown,
+    };
+
+    PortProperties {
+        name: props_get("port.name").unwrap_or_default().to_string(),
+        alias: props_get("port.alias").map(|alias| alias.to_string()),
+        channel: props_get("audio.channel").map(|channel| channel.to_string()),
+        index: props_get("port.id").and_then(|index| index.parse().ok()),
+        monitor: props_get("port.monitor") == Some("true"),
+        port_type,
+        format: props_get("format.dsp").map(|format| format.to_string()),
+    }
+}
+
 fn handle_port(
     port: &GlobalObject<ForeignDict>,
     state: &Rc<RefCell<State>>,
     sender: &Rc<Sender<PipewireMessage>>,
+    registry: &Rc<Registry>,
+    proxies: &Rc<RefCell<Proxies>>,
 ) {
     let props = port
         .props
         .as_ref()
         .expect("Port object doesn't have properties");
 
-    let name = props.get("port.name").unwrap_or_default().to_string();
+    let port_props = collect_props(props.iter());
+    let properties = port_properties(&port_props);
 
     let node_id = props
         .get("node.id")
@@ -335,31 +1284,25 @@ fn handle_port(
         .parse::<u32>()
         .expect("Couldn't parse node.id as u32");
 
-    let mut state = state.borrow_mut();
-
     let node_name = match state
+        .borrow()
         .get(node_id)
         .expect(&format!("Node with id {} was never registered", node_id))
     {
-        state::GlobalObject::Node { name } => name,
+        state::GlobalObject::Node { name, .. } => name,
         _ => {
             unreachable!()
         }
     }
     .clone();
 
-    let port_type = match props.get("port.direction") {
-        Some("in") => PortType::Input,
-        Some("out") => PortType::Output,
-        _ => PortType::Unknown,
-    };
-
-    state.add(
+    state.borrow_mut().add(
         port.id,
         state::GlobalObject::Port {
             node_name: node_name.clone(),
             node_id,
             id: port.id,
+            name: properties.name.clone(),
         },
     );
 
@@ -368,8 +1311,171 @@ fn handle_port(
             node_name,
             node_id,
             id: port.id,
-            name,
-            port_type,
+            properties,
         })
         .expect("Failed to send pipewire message");
+
+    // The port proxy is bound to receive property updates
+    let proxy: pipewire::port::Port = registry.bind(port).expect("Failed to bind port proxy");
+
+    let sender = sender.clone();
+    let state = state.clone();
+    // The first info event repeats the properties the port was added with
+    let port_props = RefCell::new(port_props);
+
+    let listener = proxy
+        .add_listener_local()
+        .info(move |info| {
+            if !info.change_mask().contains(PortChangeMask::PROPS) {
+                return;
+            }
+
+            if let Some(props) = info.props() {
+                let new_props = collect_props(props.iter());
+                if *port_props.borrow() == new_props {
+                    return;
+                }
+
+                let properties = port_properties(&new_props);
+                *port_props.borrow_mut() = new_props;
+                state.borrow_mut().update_port(info.id(), &properties.name);
+
+                sender
+                    .send(PipewireMessage::PortChanged {
+                        node_id,
+                        id: info.id(),
+                        properties,
+                    })
+                    .expect("Failed to send pipewire message");
+            }
+        })
+        .register();
+
+    proxies
+        .borrow_mut()
+        .insert(port.id, ProxyObject::Port(ProxyPort { proxy, listener }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn link_endpoint_check_table() {
+        let endpoint = |port_type, media_type, format| LinkEndpoint {
+            port_type,
+            media_type,
+            format,
+        };
+        let audio_out = endpoint(
+            PortType::Output,
+            Some(MediaType::Audio),
+            Some("32 bit float mono audio"),
+        );
+        let audio_in = endpoint(
+            PortType::Input,
+            Some(MediaType::Audio),
+            Some("32 bit float mono audio"),
+        );
+
+        for (from, to, ok) in [
+            (audio_out, audio_in, true),
+            // Either direction can be given first
+            (audio_in, audio_out, true),
+            (audio_out, audio_out, false),
+            (audio_in, audio_in, false),
+            (endpoint(PortType::Unknown, None, None), audio_in, false),
+            (
+                audio_out,
+                endpoint(PortType::Input, Some(MediaType::Midi), None),
+                false,
+            ),
+            (
+                audio_out,
+                endpoint(
+                    PortType::Input,
+                    Some(MediaType::Audio),
+                    Some("8 bit raw midi"),
+                ),
+                false,
+            ),
+            // Unknown media types and formats don't prevent linking
+            (audio_out, endpoint(PortType::Input, None, None), true),
+        ] {
+            assert_eq!(from.check(&to).is_ok(), ok, "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn client_properties_table() {
+        let cases = [
+            (props(&[]), None, None, None),
+            (
+                props(&[
+                    ("application.name", "Firefox"),
+                    ("application.process.id", "1234"),
+                    ("application.process.binary", "firefox"),
+                    ("pipewire.access", "unrestricted"),
+                ]),
+                Some("Firefox"),
+                Some(1234),
+                Some("unrestricted"),
+            ),
+            // The pid the client claims is preferred over the one of the socket
+            (
+                props(&[
+                    ("application.process.id", "1234"),
+                    ("pipewire.sec.pid", "5678"),
+                ]),
+                None,
+                Some(1234),
+                None,
+            ),
+            (
+                props(&[("pipewire.sec.pid", "5678")]),
+                None,
+                Some(5678),
+                None,
+            ),
+            (
+                props(&[
+                    ("application.process.id", "not a pid"),
+                    ("pipewire.access", "flatpak"),
+                ]),
+                None,
+                None,
+                Some("flatpak"),
+            ),
+        ];
+
+        for (props, name, pid, access) in cases {
+            let properties = client_properties(&props);
+            assert_eq!(properties.name.as_deref(), name, "{:?}", props);
+            assert_eq!(properties.pid, pid, "{:?}", props);
+            assert_eq!(properties.access.as_deref(), access, "{:?}", props);
+        }
+    }
+
+    #[test]
+    fn nodes_are_attributed_to_their_client() {
+        let stream = props(&[
+            ("media.class", "Stream/Output/Audio"),
+            ("application.name", "Firefox"),
+            ("client.id", "30"),
+        ]);
+        assert_eq!(node_properties(&stream).client_id, Some(30));
+
+        let device = props(&[("media.class", "Audio/Sink"), ("node.name", "speakers")]);
+        assert_eq!(node_properties(&device).client_id, None);
+
+        let invalid = props(&[("client.id", "")]);
+        assert_eq!(node_properties(&invalid).client_id, None);
+    }
 }
@@ -0,0 +1,368 @@
+//! Runs a single command line operation against the graph without starting the ui
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+
+use pipewire::{prelude::ReadableDict, proxy::ProxyT, Context, Core, Loop, MainLoop};
+use serde::Serialize;
+
+use super::{
+    state::{GlobalObject, State},
+    LinkEndpoint, PortType,
+};
+use crate::cli::{Command, PortPath};
+
+/// How long to wait for a new link to be set up
+const LINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+struct PortEntry<'a> {
+    id: u32,
+    node: &'a str,
+    port: &'a str,
+    direction: &'a str,
+}
+
+#[derive(Serialize)]
+struct LinkEntry<'a> {
+    id: u32,
+    output_node: &'a str,
+    output_port: &'a str,
+    input_node: &'a str,
+    input_port: &'a str,
+}
+
+pub fn run(command: &Command) -> Result<(), Box<dyn std::error::Error>> {
+    let mainloop = MainLoop::new()?;
+    let context = Context::new(&mainloop)?;
+    let core = Rc::new(context.connect(None)?);
+    let registry = Rc::new(core.get_registry()?);
+
+    let state = Rc::new(RefCell::new(State::new()));
+    // Only needed for listing and checking links, so it isn't part of the state
+    let directions = Rc::new(RefCell::new(HashMap::new()));
+
+    let _listener = registry
+        .add_listener_local()
+        .global({
+            let state = state.clone();
+            let directions = directions.clone();
+
+            move |global| {
+                let props = match &global.props {
+                    Some(props) => props,
+                    None => return,
+                };
+                let props_get = |key: &str| props.get(key).unwrap_or_default().to_string();
+                let parse_id = |key: &str| props.get(key).and_then(|id| id.parse::<u32>().ok());
+
+                let object = match global.type_ {
+                    pipewire::types::ObjectType::Node => {
+                        let props = super::collect_props(props.iter());
+                        // Nodes go by the same names as in the graph, so that paths work everywhere
+                        GlobalObject::Node {
+                            name: super::node_properties(&props).name,
+                            props,
+                        }
+                    }
+                    pipewire::types::ObjectType::Port => {
+                        let node_id = match parse_id("node.id") {
+                            Some(node_id) => node_id,
+                            None => return,
+                        };
+                        directions
+                            .borrow_mut()
+                            .insert(global.id, props_get("port.direction"));
+
+                        GlobalObject::Port {
+                            node_name: String::new(),
+                            node_id,
+                            id: global.id,
+                            name: props_get("port.name"),
+                        }
+                    }
+                    pipewire::types::ObjectType::Link => {
+                        match (parse_id("link.output.port"), parse_id("link.input.port")) {
+                            (Some(from_port), Some(to_port)) => {
+                                GlobalObject::Link { from_port, to_port }
+                            }
+                            _ => return,
+                        }
+                    }
+                    _ => return,
+                };
+
+                state.borrow_mut().add(global.id, object);
+            }
+        })
+        .register();
+
+    // Every global is announced before the initial sync completes
+    roundtrip(&mainloop, &core)?;
+
+    match command {
+        Command::Connect {
+            output,
+            input,
+            feedback,
+        } => {
+            let (from_port, to_port) = {
+                let state = state.borrow();
+                (find_port(&state, output)?, find_port(&state, input)?)
+            };
+
+            {
+                let state = state.borrow();
+                let directions = directions.borrow();
+                // The same checks as for links made in the ui, except for the format which isn't known here
+                let endpoint = |port_id| {
+                    let port_type = match directions.get(&port_id).map(String::as_str) {
+                        Some("in") => PortType::Input,
+                        Some("out") => PortType::Output,
+                        _ => PortType::Unknown,
+                    };
+                    let media_type = match state.get(port_id) {
+                        Some(GlobalObject::Port { node_id, .. }) => match state.get(*node_id) {
+                            Some(GlobalObject::Node { props, .. }) => {
+                                super::node_properties(props).media_type
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+
+                    LinkEndpoint {
+                        port_type,
+                        media_type,
+                        format: None,
+                    }
+                };
+
+                let (from, to) = (endpoint(from_port), endpoint(to_port));
+                from.check(&to)
+                    .map_err(|err| format!("Can't link {} to {}: {}", output, input, err))?;
+                if !matches!(from.port_type, PortType::Output) {
+                    return Err(format!("{} is not an output port", output).into());
+                }
+            }
+
+            if state.borrow().find_link(from_port, to_port).is_some() {
+                return Err(format!("{} is already linked to {}", output, input).into());
+            }
+            if !feedback && state.borrow().would_loop(from_port, to_port) {
+                return Err(format!(
+                    "Linking {} to {} closes a feedback loop, pass --feedback to create it anyway",
+                    output, input
+                )
+                .into());
+            }
+
+            // The command exits right away, so the link has to outlive it
+            let options = super::LinkOptions {
+                feedback: *feedback,
+                ..Default::default()
+            };
+            let proxy = super::add_link(&state, from_port, to_port, options, &core)
+                .ok_or("Couldn't create the link")?;
+            wait_for_link(&mainloop, &core, &proxy)?;
+        }
+        Command::Disconnect { output, input } => {
+            let link_id = {
+                let state = state.borrow();
+                let from_port = find_port(&state, output)?;
+                let to_port = find_port(&state, input)?;
+
+                state
+                    .find_link(from_port, to_port)
+                    .ok_or_else(|| format!("{} is not linked to {}", output, input))?
+            };
+
+            super::remove_link(link_id, &state, &registry);
+            roundtrip(&mainloop, &core)?;
+        }
+        Command::List { links, json } => {
+            let state = state.borrow();
+            let directions = directions.borrow();
+
+            let port_path = |port_id: u32| match state.get(port_id) {
+                Some(GlobalObject::Port { node_id, name, .. }) => {
+                    Some((node_name(&state, *node_id)?, name.as_str()))
+                }
+                _ => None,
+            };
+
+            if *links {
+                let mut entries = state
+                    .iter()
+                    .filter_map(|(id, object)| match object {
+                        GlobalObject::Link { from_port, to_port } => {
+                            let (output_node, output_port) = port_path(*from_port)?;
+                            let (input_node, input_port) = port_path(*to_port)?;
+
+                            Some(LinkEntry {
+                                id,
+                                output_node,
+                                output_port,
+                                input_node,
+                                input_port,
+                            })
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                entries.sort_by_key(|entry| entry.id);
+
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&entries)?);
+                } else {
+                    for entry in entries {
+                        println!(
+                            "{}:{} -> {}:{}",
+                            entry.output_node,
+                            entry.output_port,
+                            entry.input_node,
+                            entry.input_port
+                        );
+                    }
+                }
+            } else {
+                let mut entries = state
+                    .iter()
+                    .filter_map(|(id, _)| {
+                        let (node, port) = port_path(id)?;
+                        let direction = directions.get(&id).map_or("", String::as_str);
+
+                        Some(PortEntry {
+                            id,
+                            node,
+                            port,
+                            direction,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                entries.sort_by_key(|entry| entry.id);
+
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&entries)?);
+                } else {
+                    for entry in entries {
+                        println!("{}:{} ({})", entry.node, entry.port, entry.direction);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the main loop until the server has processed every request sent so far
+fn roundtrip(mainloop: &MainLoop, core: &Core) -> Result<(), Box<dyn std::error::Error>> {
+    let pending = core.sync(0)?;
+
+    let _listener = core
+        .add_listener_local()
+        .done({
+            let mainloop = mainloop.clone();
+            move |id, seq| {
+                if id == pipewire::PW_ID_CORE && seq == pending {
+                    mainloop.quit();
+                }
+            }
+        })
+        .register();
+
+    mainloop.run();
+
+    Ok(())
+}
+
+/// Runs the main loop until the link has been set up, or has failed
+fn wait_for_link(
+    mainloop: &MainLoop,
+    core: &Core,
+    proxy: &pipewire::link::Link,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = Rc::new(RefCell::new(None));
+    let finish = {
+        let result = result.clone();
+        let mainloop = mainloop.clone();
+
+        move |outcome: Result<(), String>| {
+            result.borrow_mut().get_or_insert(outcome);
+            mainloop.quit();
+        }
+    };
+
+    let _link_listener = proxy
+        .add_listener_local()
+        .info({
+            let finish = finish.clone();
+            move |info| match info.state() {
+                pipewire::link::LinkState::Error(error) => finish(Err(error.to_string())),
+                pipewire::link::LinkState::Paused | pipewire::link::LinkState::Active => {
+                    finish(Ok(()))
+                }
+                _ => {}
+            }
+        })
+        .register();
+
+    // Links the server refuses to create are reported on the core instead
+    let proxy_id = proxy.upcast_ref().id();
+    let _core_listener = core
+        .add_listener_local()
+        .error({
+            let finish = finish.clone();
+            move |id, _seq, _res, message| {
+                if id == proxy_id {
+                    finish(Err(message.to_string()));
+                }
+            }
+        })
+        .register();
+
+    let timer = mainloop.add_timer(move |_| {
+        finish(Err(
+            "Timed out waiting for the link to be set up".to_string()
+        ))
+    });
+    if let Err(err) = timer.update_timer(Some(LINK_TIMEOUT), None).into_result() {
+        log::warn!("Failed to start link timeout: {}", err);
+    }
+
+    mainloop.run();
+
+    match result.borrow_mut().take() {
+        Some(Err(err)) => Err(format!("Failed to create the link: {}", err).into()),
+        _ => Ok(()),
+    }
+}
+
+fn node_name(state: &State, node_id: u32) -> Option<&str> {
+    match state.get(node_id)? {
+        GlobalObject::Node { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+/// Several nodes can share a name, like they share a node in the graph
+fn find_port(state: &State, path: &PortPath) -> Result<u32, String> {
+    let node_exists = state
+        .iter()
+        .any(|(_, object)| matches!(object, GlobalObject::Node { name, .. } if *name == path.node));
+    if !node_exists {
+        return Err(format!("Couldn't find node {:?}", path.node));
+    }
+
+    state
+        .iter()
+        .find_map(|(id, object)| match object {
+            GlobalObject::Port { node_id, name, .. }
+                if *name == path.port && node_name(state, *node_id) == Some(path.node.as_str()) =>
+            {
+                Some(id)
+            }
+            _ => None,
+        })
+        .ok_or_else(|| format!("Couldn't find port {}", path))
+}
@@ -0,0 +1,95 @@
+//! Setting node params, pipewire-rs doesn't expose `set_param` or a pod builder so the pod is written by hand
+
+use std::{ffi::c_void, os::raw::c_char};
+
+use libspa_sys as spa_sys;
+use pipewire::proxy::ProxyT;
+
+const SPA_TYPE_FLOAT: u32 = 6;
+const SPA_TYPE_OBJECT: u32 = 15;
+
+const SPA_TYPE_OBJECT_PROPS: u32 = 0x40002;
+const SPA_PARAM_PROPS: u32 = 2;
+const SPA_PROP_VOLUME: u32 = 0x10003;
+
+/// Matches `struct pw_node_methods` from `pipewire/node.h`
+#[repr(C)]
+#[allow(dead_code)]
+struct NodeMethods {
+    version: u32,
+    add_listener: Option<
+        unsafe extern "C" fn(
+            object: *mut c_void,
+            listener: *mut spa_sys::spa_hook,
+            events: *const c_void,
+            data: *mut c_void,
+        ) -> i32,
+    >,
+    subscribe_params:
+        Option<unsafe extern "C" fn(object: *mut c_void, ids: *mut u32, n_ids: u32) -> i32>,
+    enum_params: Option<
+        unsafe extern "C" fn(
+            object: *mut c_void,
+            seq: i32,
+            id: u32,
+            start: u32,
+            num: u32,
+            filter: *const spa_sys::spa_pod,
+        ) -> i32,
+    >,
+    set_param: Option<
+        unsafe extern "C" fn(
+            object: *mut c_void,
+            id: u32,
+            flags: u32,
+            param: *const spa_sys::spa_pod,
+        ) -> i32,
+    >,
+    send_command: Option<unsafe extern "C" fn(object: *mut c_void, command: *const c_char) -> i32>,
+}
+
+/// Pods have to be 8 byte aligned
+#[repr(C, align(8))]
+struct PodBuffer([u32; 10]);
+
+/// A `Props` object pod with only the volume property set
+fn volume_pod(volume: f32) -> PodBuffer {
+    PodBuffer([
+        // Object header, the body is the object type, id and one property
+        32,
+        SPA_TYPE_OBJECT,
+        SPA_TYPE_OBJECT_PROPS,
+        SPA_PARAM_PROPS,
+        // Property key and flags
+        SPA_PROP_VOLUME,
+        0,
+        // Float pod, padded to 8 bytes
+        4,
+        SPA_TYPE_FLOAT,
+        volume.to_bits(),
+        0,
+    ])
+}
+
+/// Sets the software volume of a node, 1.0 being unity gain
+pub fn set_volume(node: &pipewire::node::Node, volume: f32) {
+    let pod = volume_pod(volume.max(0.0));
+
+    unsafe {
+        // The interface is the first member of a proxy
+        let iface = node.upcast_ref().as_ptr() as *mut spa_sys::spa_interface;
+        let methods = (*iface).cb.funcs as *const NodeMethods;
+
+        if let Some(set_param) = methods.as_ref().and_then(|methods| methods.set_param) {
+            let res = set_param(
+                (*iface).cb.data,
+                SPA_PARAM_PROPS,
+                0,
+                pod.0.as_ptr() as *const spa_sys::spa_pod,
+            );
+            if res < 0 {
+                log::error!("Failed to set volume: {}", res);
+            }
+        }
+    }
+}
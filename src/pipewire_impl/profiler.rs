@@ -0,0 +1,485 @@
+//! The profiler interface of `libpipewire-module-profiler` isn't wrapped by pipewire-rs,
+//! so this provides a minimal proxy for it along with a reader for the SPA pods it emits
+
+use std::{collections::HashMap, ffi::c_void, mem};
+
+use libspa_sys as spa_sys;
+use pipewire::{proxy::Proxy, proxy::ProxyT, types::ObjectType};
+
+use super::hook::ObjectListener;
+
+const PW_VERSION_PROFILER_EVENTS: u32 = 0;
+
+const SPA_TYPE_INT: u32 = 4;
+const SPA_TYPE_LONG: u32 = 5;
+const SPA_TYPE_FLOAT: u32 = 6;
+const SPA_TYPE_DOUBLE: u32 = 7;
+const SPA_TYPE_STRING: u32 = 8;
+const SPA_TYPE_FRACTION: u32 = 11;
+const SPA_TYPE_STRUCT: u32 = 14;
+const SPA_TYPE_OBJECT: u32 = 15;
+
+const SPA_TYPE_OBJECT_PROFILER: u32 = 0x4000a;
+
+const SPA_PROFILER_INFO: u32 = 0x10001;
+const SPA_PROFILER_CLOCK: u32 = 0x10002;
+const SPA_PROFILER_DRIVER_BLOCK: u32 = 0x10003;
+const SPA_PROFILER_FOLLOWER_BLOCK: u32 = 0x20001;
+
+/// Matches `struct pw_profiler_events` from `pipewire/extensions/profiler.h`
+#[repr(C)]
+#[allow(dead_code)]
+struct ProfilerEvents {
+    version: u32,
+    profile: Option<unsafe extern "C" fn(data: *mut c_void, pod: *const spa_sys::spa_pod)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DriverProfile {
+    pub id: u32,
+    pub name: String,
+    /// Quantum in samples
+    pub quantum: u64,
+    pub rate: u32,
+    /// Fraction of the time available in a cycle that was used by the whole graph
+    pub cpu_load: f32,
+    pub xruns: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NodeProfile {
+    pub driver_id: u32,
+    /// Time between being signalled and waking up, in seconds
+    pub wait: f64,
+    /// Time spent processing, in seconds
+    pub busy: f64,
+    /// Fraction of the cycle spent processing
+    pub load: f32,
+    /// Only reported for followers by newer pipewire versions
+    pub xruns: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub drivers: HashMap<u32, DriverProfile>,
+    pub nodes: HashMap<u32, NodeProfile>,
+}
+
+pub struct Profiler {
+    proxy: Proxy,
+}
+
+impl ProxyT for Profiler {
+    fn type_() -> ObjectType {
+        ObjectType::Profiler
+    }
+
+    fn upcast(self) -> Proxy {
+        self.proxy
+    }
+
+    fn upcast_ref(&self) -> &Proxy {
+        &self.proxy
+    }
+
+    unsafe fn from_proxy_unchecked(proxy: Proxy) -> Self
+    where
+        Self: Sized,
+    {
+        Self { proxy }
+    }
+}
+
+impl Profiler {
+    pub fn add_listener_local<F: Fn(Profile) + 'static>(&self, callback: F) -> ObjectListener {
+        unsafe extern "C" fn profile<F: Fn(Profile)>(
+            data: *mut c_void,
+            pod: *const spa_sys::spa_pod,
+        ) {
+            if data.is_null() || pod.is_null() {
+                return;
+            }
+            let callback = &*(data as *const F);
+
+            let size = (*pod).size as usize + mem::size_of::<spa_sys::spa_pod>();
+            let bytes = std::slice::from_raw_parts(pod as *const u8, size);
+
+            match parse_profile(bytes) {
+                Some(profile) => callback(profile),
+                None => log::warn!("Failed to parse profiler data"),
+            }
+        }
+
+        let events = ProfilerEvents {
+            version: PW_VERSION_PROFILER_EVENTS,
+            profile: Some(profile::<F>),
+        };
+
+        unsafe { ObjectListener::add(&self.proxy, events, callback) }
+    }
+}
+
+/// A POD value, only the types used by the profiler are supported
+#[derive(Debug)]
+enum Pod<'a> {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(&'a str),
+    Fraction(u32, u32),
+    Struct(Vec<Pod<'a>>),
+    Object {
+        type_: u32,
+        props: Vec<(u32, Pod<'a>)>,
+    },
+    Other,
+}
+
+impl<'a> Pod<'a> {
+    fn as_int(&self) -> Option<i32> {
+        match self {
+            Pod::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+    fn as_long(&self) -> Option<i64> {
+        match self {
+            Pod::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+    fn as_float(&self) -> Option<f32> {
+        match self {
+            Pod::Float(value) => Some(*value),
+            Pod::Double(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+    fn as_str(&self) -> Option<&'a str> {
+        match self {
+            Pod::String(value) => Some(value),
+            _ => None,
+        }
+    }
+    fn as_fraction(&self) -> Option<(u32, u32)> {
+        match self {
+            Pod::Fraction(num, denom) => Some((*num, *denom)),
+            _ => None,
+        }
+    }
+    fn as_struct(&self) -> Option<&[Pod<'a>]> {
+        match self {
+            Pod::Struct(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset + 8)?;
+    Some(u64::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+/// Parses a single pod, returning it along with the number of bytes it occupies including padding
+fn parse_pod(bytes: &[u8]) -> Option<(Pod, usize)> {
+    let size = read_u32(bytes, 0)? as usize;
+    let type_ = read_u32(bytes, 4)?;
+    let body = bytes.get(8..8 + size)?;
+
+    let pod = match type_ {
+        SPA_TYPE_INT => Pod::Int(read_u32(body, 0)? as i32),
+        SPA_TYPE_LONG => Pod::Long(read_u64(body, 0)? as i64),
+        SPA_TYPE_FLOAT => Pod::Float(f32::from_bits(read_u32(body, 0)?)),
+        SPA_TYPE_DOUBLE => Pod::Double(f64::from_bits(read_u64(body, 0)?)),
+        SPA_TYPE_STRING => {
+            let end = body.iter().position(|&c| c == 0).unwrap_or(body.len());
+            Pod::String(std::str::from_utf8(&body[..end]).ok()?)
+        }
+        SPA_TYPE_FRACTION => Pod::Fraction(read_u32(body, 0)?, read_u32(body, 4)?),
+        SPA_TYPE_STRUCT => {
+            let mut fields = Vec::new();
+            let mut offset = 0;
+            while offset < body.len() {
+                let (field, len) = parse_pod(&body[offset..])?;
+                fields.push(field);
+                offset += len;
+            }
+            Pod::Struct(fields)
+        }
+        SPA_TYPE_OBJECT => {
+            let object_type = read_u32(body, 0)?;
+            let mut props = Vec::new();
+            // Skip the object type and id
+            let mut offset = 8;
+            while offset < body.len() {
+                let key = read_u32(body, offset)?;
+                // Skip the key and flags
+                let (value, len) = parse_pod(body.get(offset + 8..)?)?;
+                props.push((key, value));
+                offset += 8 + len;
+            }
+            Pod::Object {
+                type_: object_type,
+                props,
+            }
+        }
+        _ => Pod::Other,
+    };
+
+    // Pods are aligned to 8 bytes
+    let len = (8 + size + 7) & !7;
+    Some((pod, len))
+}
+
+/// Parses the struct of profiler objects sent on every profile event, each object describes one cycle of a driver
+fn parse_profile(bytes: &[u8]) -> Option<Profile> {
+    let (pod, _) = parse_pod(bytes)?;
+
+    let mut profile = Profile::default();
+
+    for object in pod.as_struct()? {
+        let props = match object {
+            Pod::Object { type_, props } if *type_ == SPA_TYPE_OBJECT_PROFILER => props,
+            _ => continue,
+        };
+
+        let mut cpu_load = 0.0;
+        let mut driver_xruns = 0;
+        // Duration of the cycle in nanoseconds, quantum and rate
+        let mut clock = None;
+        let mut driver = None;
+
+        for (key, value) in props {
+            let fields = match value.as_struct() {
+                Some(fields) => fields,
+                None => continue,
+            };
+
+            match *key {
+                SPA_PROFILER_INFO => {
+                    cpu_load = fields.get(1).and_then(Pod::as_float).unwrap_or_default();
+                    driver_xruns = fields.get(4).and_then(Pod::as_int).unwrap_or_default();
+                }
+                SPA_PROFILER_CLOCK => {
+                    let name = fields.get(2).and_then(Pod::as_str).unwrap_or_default();
+                    let (num, denom) = fields.get(4).and_then(Pod::as_fraction)?;
+                    let duration = fields.get(6).and_then(Pod::as_long)?.max(0) as u64;
+
+                    let period = if denom != 0 {
+                        duration as f64 * num as f64 / denom as f64
+                    } else {
+                        0.0
+                    };
+                    clock = Some((name.to_string(), duration, denom, period));
+                }
+                SPA_PROFILER_DRIVER_BLOCK | SPA_PROFILER_FOLLOWER_BLOCK => {
+                    let id = fields.first().and_then(Pod::as_int)? as u32;
+                    let signal = fields.get(3).and_then(Pod::as_long)?;
+                    let awake = fields.get(4).and_then(Pod::as_long)?;
+                    let finish = fields.get(5).and_then(Pod::as_long)?;
+                    let xruns = fields.get(8).and_then(Pod::as_int);
+
+                    if *key == SPA_PROFILER_DRIVER_BLOCK {
+                        driver = Some(id);
+                    }
+
+                    let period = clock.as_ref().map(|clock| clock.3).unwrap_or_default();
+                    // Nodes that didn't run this cycle have timestamps which are not ordered
+                    let wait = (awake - signal).max(0) as f64 / 1e9;
+                    let busy = (finish - awake).max(0) as f64 / 1e9;
+                    let load = if period > 0.0 { busy / period } else { 0.0 };
+
+                    profile.nodes.insert(
+                        id,
+                        NodeProfile {
+                            driver_id: driver.unwrap_or(id),
+                            wait,
+                            busy,
+                            load: load as f32,
+                            xruns,
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        if let (Some(id), Some((name, quantum, rate, _))) = (driver, clock) {
+            profile.drivers.insert(
+                id,
+                DriverProfile {
+                    id,
+                    name,
+                    quantum,
+                    rate,
+                    cpu_load,
+                    xruns: driver_xruns,
+                },
+            );
+        }
+    }
+
+    Some(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod(type_: u32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(body.len() as u32).to_ne_bytes());
+        bytes.extend_from_slice(&type_.to_ne_bytes());
+        bytes.extend_from_slice(body);
+        bytes.resize((bytes.len() + 7) & !7, 0);
+        bytes
+    }
+    fn int(value: i32) -> Vec<u8> {
+        pod(SPA_TYPE_INT, &value.to_ne_bytes())
+    }
+    fn long(value: i64) -> Vec<u8> {
+        pod(SPA_TYPE_LONG, &value.to_ne_bytes())
+    }
+    fn float(value: f32) -> Vec<u8> {
+        pod(SPA_TYPE_FLOAT, &value.to_bits().to_ne_bytes())
+    }
+    fn string(value: &str) -> Vec<u8> {
+        let mut body = value.as_bytes().to_vec();
+        body.push(0);
+        pod(SPA_TYPE_STRING, &body)
+    }
+    fn fraction(num: u32, denom: u32) -> Vec<u8> {
+        let mut body = num.to_ne_bytes().to_vec();
+        body.extend_from_slice(&denom.to_ne_bytes());
+        pod(SPA_TYPE_FRACTION, &body)
+    }
+    fn structure(fields: &[Vec<u8>]) -> Vec<u8> {
+        pod(SPA_TYPE_STRUCT, &fields.concat())
+    }
+    fn object(type_: u32, props: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut body = type_.to_ne_bytes().to_vec();
+        body.extend_from_slice(&0u32.to_ne_bytes());
+        for (key, value) in props {
+            body.extend_from_slice(&key.to_ne_bytes());
+            body.extend_from_slice(&0u32.to_ne_bytes());
+            body.extend_from_slice(value);
+        }
+        pod(SPA_TYPE_OBJECT, &body)
+    }
+
+    /// A driver block or follower block, as sent by `libpipewire-module-profiler`
+    fn block(id: i32, signal: i64, awake: i64, finish: i64, xruns: i32) -> Vec<u8> {
+        structure(&[
+            int(id),
+            string("node"),
+            long(0),
+            long(signal),
+            long(awake),
+            long(finish),
+            int(0),
+            fraction(0, 1),
+            int(xruns),
+        ])
+    }
+
+    #[test]
+    fn parses_scalars() {
+        let (pod, len) = parse_pod(&int(-3)).unwrap();
+        assert_eq!(pod.as_int(), Some(-3));
+        assert_eq!(len, 16);
+
+        let (pod, _) = parse_pod(&long(1 << 40)).unwrap();
+        assert_eq!(pod.as_long(), Some(1 << 40));
+
+        let (pod, _) = parse_pod(&float(0.5)).unwrap();
+        assert_eq!(pod.as_float(), Some(0.5));
+
+        let (pod, len) = parse_pod(&string("alsa_output")).unwrap();
+        assert_eq!(pod.as_str(), Some("alsa_output"));
+        assert_eq!(len, 24);
+
+        let (pod, _) = parse_pod(&fraction(1, 48000)).unwrap();
+        assert_eq!(pod.as_fraction(), Some((1, 48000)));
+    }
+
+    #[test]
+    fn parses_struct_fields_after_padding() {
+        let bytes = structure(&[string("a"), int(1), long(2)]);
+        let (pod, len) = parse_pod(&bytes).unwrap();
+
+        let fields = pod.as_struct().unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0].as_str(), Some("a"));
+        assert_eq!(fields[1].as_int(), Some(1));
+        assert_eq!(fields[2].as_long(), Some(2));
+        assert_eq!(len, bytes.len());
+    }
+
+    #[test]
+    fn rejects_truncated_pods() {
+        let bytes = structure(&[int(1), long(2)]);
+
+        assert!(parse_pod(&bytes[..4]).is_none());
+        assert!(parse_pod(&bytes[..bytes.len() - 8]).is_none());
+    }
+
+    #[test]
+    fn parses_profile() {
+        let info = structure(&[long(1), float(0.25), float(0.0), float(0.0), int(4)]);
+        let clock = structure(&[
+            int(0),
+            int(30),
+            string("alsa_output"),
+            long(0),
+            fraction(1, 48000),
+            long(0),
+            long(1024),
+        ]);
+        let bytes = structure(&[object(
+            SPA_TYPE_OBJECT_PROFILER,
+            &[
+                (SPA_PROFILER_INFO, info),
+                (SPA_PROFILER_CLOCK, clock),
+                (SPA_PROFILER_DRIVER_BLOCK, block(30, 0, 1_000, 2_000, 4)),
+                (
+                    SPA_PROFILER_FOLLOWER_BLOCK,
+                    block(42, 1_000, 3_000, 1_003_000, 1),
+                ),
+            ],
+        )]);
+
+        let profile = parse_profile(&bytes).unwrap();
+
+        let driver = &profile.drivers[&30];
+        assert_eq!(driver.name, "alsa_output");
+        assert_eq!(driver.quantum, 1024);
+        assert_eq!(driver.rate, 48000);
+        assert_eq!(driver.cpu_load, 0.25);
+        assert_eq!(driver.xruns, 4);
+
+        let follower = &profile.nodes[&42];
+        assert_eq!(follower.driver_id, 30);
+        assert!((follower.wait - 2e-6).abs() < 1e-12);
+        assert!((follower.busy - 1e-3).abs() < 1e-12);
+        // The cycle lasts 1024 samples at 48kHz
+        assert!((follower.load - 0.046875).abs() < 1e-6);
+        assert_eq!(follower.xruns, Some(1));
+
+        assert_eq!(profile.nodes[&30].driver_id, 30);
+    }
+
+    #[test]
+    fn skips_other_objects() {
+        let bytes = structure(&[int(1), object(0x1234, &[])]);
+        let profile = parse_profile(&bytes).unwrap();
+
+        assert!(profile.drivers.is_empty());
+        assert!(profile.nodes.is_empty());
+    }
+}
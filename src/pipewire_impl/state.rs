@@ -1,15 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub enum GlobalObject {
     Node {
         name: String,
+        props: HashMap<String, String>,
+    },
+    Link {
+        from_port: u32,
+        to_port: u32,
     },
-    Link,
     Port {
         node_name: String,
         node_id: u32,
         id: u32,
+        name: String,
     },
+    Client,
 }
 
 /// For internal state tracking, this has to be done because pipewire only provides ids of the objects it removes,
@@ -33,4 +39,190 @@ impl State {
     pub fn remove(&mut self, id: u32) -> Option<GlobalObject> {
         self.objects.remove(&id)
     }
+    /// Updates the name and properties of a node along with the node name stored in its ports,
+    /// returns false if the properties didn't change
+    pub fn update_node(
+        &mut self,
+        id: u32,
+        new_name: &str,
+        new_props: HashMap<String, String>,
+    ) -> bool {
+        match self.objects.get_mut(&id) {
+            Some(GlobalObject::Node { name, props }) if *props != new_props => {
+                *name = new_name.to_string();
+                *props = new_props;
+            }
+            _ => return false,
+        }
+
+        for object in self.objects.values_mut() {
+            if let GlobalObject::Port {
+                node_id, node_name, ..
+            } = object
+            {
+                if *node_id == id {
+                    *node_name = new_name.to_string();
+                }
+            }
+        }
+
+        true
+    }
+    pub fn update_port(&mut self, id: u32, new_name: &str) {
+        if let Some(GlobalObject::Port { name, .. }) = self.objects.get_mut(&id) {
+            *name = new_name.to_string();
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &GlobalObject)> {
+        self.objects.iter().map(|(id, object)| (*id, object))
+    }
+    /// Finds the id of a node using its `node.name` property
+    pub fn find_node(&self, node_name: &str) -> Option<u32> {
+        self.iter().find_map(|(id, object)| match object {
+            GlobalObject::Node { props, .. }
+                if props.get("node.name").map(String::as_str) == Some(node_name) =>
+            {
+                Some(id)
+            }
+            _ => None,
+        })
+    }
+    /// Finds the id of a port using the id of the node it belongs to and its `port.name` property
+    pub fn find_port(&self, node_id: u32, port_name: &str) -> Option<u32> {
+        self.iter().find_map(|(id, object)| match object {
+            GlobalObject::Port {
+                node_id: port_node_id,
+                name,
+                ..
+            } if *port_node_id == node_id && name == port_name => Some(id),
+            _ => None,
+        })
+    }
+    /// Finds the id of the link between two ports
+    pub fn find_link(&self, from_port: u32, to_port: u32) -> Option<u32> {
+        self.iter().find_map(|(id, object)| match object {
+            GlobalObject::Link {
+                from_port: link_from_port,
+                to_port: link_to_port,
+            } if *link_from_port == from_port && *link_to_port == to_port => Some(id),
+            _ => None,
+        })
+    }
+    /// Name of the node a port belongs to
+    fn port_node_name(&self, port_id: u32) -> Option<&str> {
+        match self.get(port_id)? {
+            GlobalObject::Port { node_id, .. } => match self.get(*node_id)? {
+                GlobalObject::Node { name, .. } => Some(name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    /// Whether linking the two ports would close a feedback loop,
+    /// nodes sharing a name are one node the same as in the graph
+    pub fn would_loop(&self, from_port: u32, to_port: u32) -> bool {
+        let (from_node, to_node) =
+            match (self.port_node_name(from_port), self.port_node_name(to_port)) {
+                (Some(from_node), Some(to_node)) => (from_node, to_node),
+                _ => return false,
+            };
+
+        let mut adj_list = HashMap::<&str, Vec<&str>>::new();
+        for (_, object) in self.iter() {
+            if let GlobalObject::Link { from_port, to_port } = object {
+                if let (Some(from), Some(to)) = (
+                    self.port_node_name(*from_port),
+                    self.port_node_name(*to_port),
+                ) {
+                    adj_list.entry(from).or_default().push(to);
+                }
+            }
+        }
+
+        // A node linked to itself, like a sink's monitor to its own playback, is a loop as well
+        let mut visited = HashSet::new();
+        let mut stack = vec![to_node];
+        while let Some(node) = stack.pop() {
+            if node == from_node {
+                return true;
+            }
+            if visited.insert(node) {
+                stack.extend(adj_list.get(node).into_iter().flatten());
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nodes named after the given names, each with an output port with id `id + 100` and an input port with id `id + 200`
+    fn state(nodes: &[(u32, &str)], links: &[(u32, u32)]) -> State {
+        let mut state = State::new();
+        for &(id, name) in nodes {
+            state.add(
+                id,
+                GlobalObject::Node {
+                    name: name.to_string(),
+                    props: HashMap::new(),
+                },
+            );
+            for (port_id, port_name) in [(id + 100, "output"), (id + 200, "input")] {
+                state.add(
+                    port_id,
+                    GlobalObject::Port {
+                        node_name: String::new(),
+                        node_id: id,
+                        id: port_id,
+                        name: port_name.to_string(),
+                    },
+                );
+            }
+        }
+        for (i, &(from, to)) in links.iter().enumerate() {
+            state.add(
+                1000 + i as u32,
+                GlobalObject::Link {
+                    from_port: from + 100,
+                    to_port: to + 200,
+                },
+            );
+        }
+        state
+    }
+
+    #[test]
+    fn would_loop_table() {
+        let nodes = [(1, "a"), (2, "b"), (3, "c"), (4, "a")];
+        // (links, from, to, loops)
+        let cases = [
+            (&[][..], 1, 2, false),
+            (&[][..], 1, 1, true),
+            (&[(1, 2)][..], 2, 1, true),
+            (&[(1, 2), (2, 3)][..], 3, 1, true),
+            (&[(1, 2), (2, 3)][..], 1, 3, false),
+            // Nodes 1 and 4 share a name, so they are one node
+            (&[(1, 2)][..], 2, 4, true),
+        ];
+
+        for (links, from, to, loops) in cases {
+            assert_eq!(
+                state(&nodes, links).would_loop(from + 100, to + 200),
+                loops,
+                "{:?} + ({}, {})",
+                links,
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_ports_dont_loop() {
+        let state = state(&[(1, "a")], &[]);
+        assert!(!state.would_loop(101, 999));
+    }
 }
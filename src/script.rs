@@ -0,0 +1,156 @@
+//! Routing scripts written in Rhai
+//!
+//! A script defines handlers for graph events and calls actions which are carried out by the ui thread:
+//! ```rhai
+//! fn on_port_added(node, port) {
+//!     if node == "ZOOM VoiceEngine" && port == "input_MONO" {
+//!         connect("Noise Canceling Source:capture_MONO", "ZOOM VoiceEngine:input_MONO");
+//!     }
+//! }
+//! ```
+//! Ports are given as `<node>:<port>` paths, the same as on the command line and the control socket
+
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Scope, AST};
+
+/// An action requested by the script
+#[derive(Debug)]
+pub enum ScriptAction {
+    Connect { output: String, input: String },
+    Disconnect { output: String, input: String },
+    SetVolume { node: String, volume: f32 },
+}
+
+/// `$XDG_CONFIG_HOME/pw-viz/script.rhai`
+pub fn default_path() -> PathBuf {
+    crate::pipewire_impl::xdg_config_dir()
+        .join(env!("CARGO_PKG_NAME"))
+        .join("script.rhai")
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: Option<AST>,
+    scope: Scope<'static>,
+    actions: Rc<RefCell<Vec<ScriptAction>>>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::new();
+
+        engine.on_print(|text| log::info!("[script] {}", text));
+
+        {
+            let actions = actions.clone();
+            engine.register_fn("connect", move |output: &str, input: &str| {
+                actions.borrow_mut().push(ScriptAction::Connect {
+                    output: output.to_string(),
+                    input: input.to_string(),
+                })
+            });
+        }
+        {
+            let actions = actions.clone();
+            engine.register_fn("disconnect", move |output: &str, input: &str| {
+                actions.borrow_mut().push(ScriptAction::Disconnect {
+                    output: output.to_string(),
+                    input: input.to_string(),
+                })
+            });
+        }
+        {
+            let actions = actions.clone();
+            engine.register_fn("set_volume", move |node: &str, volume: f64| {
+                actions.borrow_mut().push(ScriptAction::SetVolume {
+                    node: node.to_string(),
+                    volume: volume as f32,
+                })
+            });
+        }
+
+        Self {
+            engine,
+            ast: None,
+            scope: Scope::new(),
+            actions,
+        }
+    }
+
+    /// Loads the script at `path` replacing the current one, the top level statements of the script are run once
+    pub fn load(&mut self, path: &std::path::Path) {
+        self.ast = None;
+        self.scope = Scope::new();
+
+        if !path.exists() {
+            log::debug!("No script found at {}", path.display());
+            return;
+        }
+
+        let ast = match self.engine.compile_file(path.to_path_buf()) {
+            Ok(ast) => ast,
+            Err(err) => {
+                log::error!("Failed to compile script {}: {}", path.display(), err);
+                return;
+            }
+        };
+
+        if let Err(err) = self.engine.run_ast_with_scope(&mut self.scope, &ast) {
+            log::error!("Failed to run script {}: {}", path.display(), err);
+            return;
+        }
+
+        log::info!("Loaded script {}", path.display());
+        self.ast = Some(ast);
+    }
+
+    /// Calls the handler of an event if the script defines it
+    fn call(&mut self, handler: &str, args: impl FuncArgs) {
+        let ast = match &self.ast {
+            Some(ast) => ast,
+            None => return,
+        };
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == handler)
+        {
+            return;
+        }
+
+        // The top level statements were already run when the script was loaded
+        let options = CallFnOptions::new().eval_ast(false);
+
+        if let Err(err) = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            ast,
+            handler,
+            args,
+        ) {
+            log::error!("Script error in {}: {}", handler, err);
+        }
+    }
+
+    pub fn on_node_added(&mut self, node: &str) {
+        self.call("on_node_added", (node.to_string(),));
+    }
+    pub fn on_node_removed(&mut self, node: &str) {
+        self.call("on_node_removed", (node.to_string(),));
+    }
+    pub fn on_port_added(&mut self, node: &str, port: &str) {
+        self.call("on_port_added", (node.to_string(), port.to_string()));
+    }
+    pub fn on_link_added(&mut self, output: &str, input: &str) {
+        self.call("on_link_added", (output.to_string(), input.to_string()));
+    }
+    pub fn on_link_removed(&mut self, output: &str, input: &str) {
+        self.call("on_link_removed", (output.to_string(), input.to_string()));
+    }
+
+    /// Actions requested by the script since the last call
+    pub fn take_actions(&mut self) -> Vec<ScriptAction> {
+        std::mem::take(&mut *self.actions.borrow_mut())
+    }
+}
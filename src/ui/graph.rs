@@ -1,12 +1,64 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
 use egui_nodes::{LinkArgs, NodeArgs, NodeConstructor};
+use serde::{Deserialize, Serialize};
 
-use crate::pipewire_impl::MediaType;
+use crate::pipewire_impl::{
+    LinkEndpoint, LinkProperties, NodeProperties, NodeState, PortProperties, PortType, Profile,
+};
 
 use super::id::Id;
 
-use super::{link::Link, node::Node, port::Port, Theme};
+use super::{
+    link::{path_distance, path_midpoint, Link, LinkStyle},
+    node::{DrawContext, Node, NodeLists},
+    palette::PortEntry,
+    port::{Port, PortFilters, PortLabels},
+    Theme,
+};
+
+/// How long links that pipewire failed to create are shown for
+const FAILED_LINK_DURATION: Duration = Duration::from_secs(4);
+
+const MINIMAP_SIZE: egui::Vec2 = egui::vec2(220.0, 150.0);
+const MINIMAP_MARGIN: f32 = 10.0;
+
+/// How close the pointer has to be to a link drawn by pw-viz to hover it
+const LINK_HOVER_DISTANCE: f32 = 6.0;
+
+/// How the graph is drawn
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewOptions {
+    pub show_minimap: bool,
+    pub link_style: LinkStyle,
+    /// Parallel links between the same two nodes are drawn as a single edge
+    pub bundle_links: bool,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        Self {
+            show_minimap: true,
+            link_style: LinkStyle::Bezier,
+            bundle_links: false,
+        }
+    }
+}
+
+fn dim(color: egui::Color32) -> egui::Color32 {
+    color.linear_multiply(0.25)
+}
+
+/// Nodes and links upstream and downstream of the hovered node or link
+struct SignalPath {
+    nodes: HashSet<Id>,
+    links: HashSet<u32>,
+}
 
 /// Represents changes to any links that might have happend in the ui
 /// These changes are used to send updates to the pipewire thread
@@ -18,12 +70,41 @@ pub enum LinkUpdate {
         to_node: u32,
     },
     Removed(u32),
+    /// A link was dragged between ports that can't be linked
+    Invalid(String),
 }
 
 pub struct Graph {
     nodes_ctx: egui_nodes::Context,
     nodes: HashMap<Id, Node>,  //Node id to Node
     links: HashMap<u32, Link>, //Link id to Link
+    /// Nodes owned by this client are drawn with a highlighted titlebar
+    highlighted_client: Option<u32>,
+    /// Node and port selected with the keyboard
+    focused_node: Option<Id>,
+    focused_port: Option<u32>,
+    /// Pin a link is currently being dragged from
+    link_source: Option<u32>,
+    /// Ports of links that couldn't be created, along with when they failed
+    failed_links: Vec<(u32, u32, Instant)>,
+    /// Everything else is dimmed while a node or link is hovered
+    signal_path: Option<SignalPath>,
+    /// Positions of the current workspace, used for nodes which haven't been placed yet
+    saved_positions: HashMap<String, egui::Pos2>,
+    /// Comma separated parts of node names, only matching nodes are shown
+    node_filter: String,
+    /// Screen space rect of the canvas in the last frame
+    canvas: Option<egui::Rect>,
+    /// Panning of the canvas, egui_nodes doesn't expose it so it's measured after every frame
+    panning: egui::Vec2,
+    /// Nodes which passed the filter and aren't hidden in the last frame
+    visible_nodes: HashSet<Id>,
+    /// Paths of the links drawn by pw-viz in the last frame along with the links on each path, several for a bundle.
+    /// egui_nodes only knows these links as straight lines, so they are hit-tested against their paths instead
+    custom_paths: Vec<(Vec<u32>, Vec<egui::Pos2>)>,
+    /// Links drawn by pw-viz under the pointer, a bundle is hovered and selected as a whole
+    hovered_custom_links: Vec<u32>,
+    selected_custom_links: Vec<u32>,
 }
 
 impl Graph {
@@ -39,6 +120,20 @@ impl Graph {
             nodes_ctx,
             nodes: HashMap::new(),
             links: HashMap::new(),
+            highlighted_client: None,
+            focused_node: None,
+            focused_port: None,
+            link_source: None,
+            failed_links: Vec::new(),
+            signal_path: None,
+            saved_positions: HashMap::new(),
+            node_filter: String::new(),
+            canvas: None,
+            panning: egui::Vec2::ZERO,
+            visible_nodes: HashSet::new(),
+            custom_paths: Vec::new(),
+            hovered_custom_links: Vec::new(),
+            selected_custom_links: Vec::new(),
         }
     }
     fn get_or_create_node(&mut self, name: String) -> &mut Node {
@@ -49,15 +144,9 @@ impl Graph {
             Node::new(id, name)
         })
     }
-    pub fn add_node(
-        &mut self,
-        name: String,
-        id: u32,
-        description: Option<String>,
-        media_type: Option<MediaType>,
-    ) {
-        self.get_or_create_node(name)
-            .add_pw_node(id, description, media_type)
+    pub fn add_node(&mut self, id: u32, properties: NodeProperties) {
+        self.get_or_create_node(properties.name.clone())
+            .add_pw_node(id, properties)
     }
     pub fn remove_node(&mut self, name: &str, id: u32) {
         let mut remove_ui_node = false;
@@ -78,6 +167,63 @@ impl Graph {
             log::debug!("Removing node {}", removed_node.name());
         }
     }
+    pub fn change_node(&mut self, id: u32, properties: NodeProperties) {
+        let old_id = match self.find_node(id) {
+            Some(node) => node.id(),
+            None => {
+                log::warn!("Pipewire node with id: {} was never added", id);
+                return;
+            }
+        };
+        let new_id = Id::new(&properties.name);
+
+        let old_node = self.nodes.get_mut(&old_id).expect("Node was never added");
+
+        if old_id == new_id {
+            old_node.update_pw_node(id, properties);
+            return;
+        }
+
+        // Ui nodes are looked up by name, so a renamed pipewire node has to be moved to another ui node
+        let pw_node = old_node
+            .take_pw_node(id)
+            .expect("Pipewire node was never added");
+
+        if old_node.is_empty() {
+            self.nodes.remove(&old_id);
+        }
+
+        let port_ids = pw_node.port_ids();
+
+        let new_node = self.get_or_create_node(properties.name.clone());
+        new_node.insert_pw_node(pw_node);
+        new_node.update_pw_node(id, properties);
+
+        for link in self.links.values_mut() {
+            if port_ids.contains(&link.from_port) {
+                link.from_node = new_id;
+            }
+            if port_ids.contains(&link.to_port) {
+                link.to_node = new_id;
+            }
+        }
+    }
+    pub fn change_port(&mut self, node_id: u32, id: u32, properties: PortProperties) {
+        match self
+            .nodes
+            .values_mut()
+            .find(|node| node.has_pw_node(node_id))
+        {
+            Some(node) => node.change_port(node_id, id, properties),
+            None => log::warn!("Pipewire node with id: {} was never added", node_id),
+        }
+    }
+    pub fn set_node_state(&mut self, id: u32, state: NodeState) {
+        match self.nodes.values_mut().find(|node| node.has_pw_node(id)) {
+            Some(node) => node.set_state(id, state),
+            None => log::warn!("Pipewire node with id: {} was never added", id),
+        }
+    }
     pub fn add_port(&mut self, node_name: String, node_id: u32, port: Port) {
         self.get_or_create_node(node_name).add_port(node_id, port)
     }
@@ -95,6 +241,7 @@ impl Graph {
         to_node_name: String,
         from_port: u32,
         to_port: u32,
+        properties: LinkProperties,
     ) {
         log::debug!(
             "{}.{}->{}.{}",
@@ -126,9 +273,57 @@ impl Graph {
                 from_port,
                 to_port,
                 active: true,
+                properties,
             },
         );
     }
+    pub fn change_link(&mut self, id: u32, properties: LinkProperties) {
+        match self.links.get_mut(&id) {
+            Some(link) => link.properties = properties,
+            None => log::warn!("Link with id {} doesn't exist", id),
+        }
+    }
+    /// The link selected in the graph, if only one is selected
+    pub fn selected_link(&self) -> Option<&Link> {
+        match self.selected_links()[..] {
+            [link] => Some(link),
+            _ => None,
+        }
+    }
+    /// Links selected in the graph, every link of a selected bundle
+    pub fn selected_links(&self) -> Vec<&Link> {
+        if !self.selected_custom_links.is_empty() {
+            return self
+                .selected_custom_links
+                .iter()
+                .filter_map(|id| self.links.get(id))
+                .collect();
+        }
+
+        // egui_nodes selects links drawn by pw-viz along the wrong line, so its selection of them is ignored
+        self.nodes_ctx
+            .get_selected_links()
+            .into_iter()
+            .map(|id| id as u32)
+            .filter(|id| !self.is_custom_link(*id))
+            .filter_map(|id| self.links.get(&id))
+            .collect()
+    }
+    fn is_custom_link(&self, id: u32) -> bool {
+        self.custom_paths
+            .iter()
+            .any(|(links, _)| links.contains(&id))
+    }
+    /// Links on the path drawn by pw-viz closest to `pointer`, if it's close enough to one
+    fn custom_links_at(&self, pointer: egui::Pos2) -> Vec<u32> {
+        self.custom_paths
+            .iter()
+            .map(|(links, path)| (links, path_distance(path, pointer)))
+            .filter(|(_, distance)| *distance <= LINK_HOVER_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(links, _)| links.clone())
+            .unwrap_or_default()
+    }
     pub fn remove_link(&mut self, id: u32) -> Option<Link> {
         let removed = self.links.remove(&id);
         match removed {
@@ -137,6 +332,239 @@ impl Graph {
         }
         removed
     }
+    /// Finds the ui node which contains the pipewire node with the given id
+    pub fn find_node(&self, pw_node_id: u32) -> Option<&Node> {
+        self.nodes
+            .values()
+            .find(|node| node.has_pw_node(pw_node_id))
+    }
+    /// Ui nodes containing a pipewire node owned by the client
+    pub fn client_nodes(&self, client_id: u32) -> impl Iterator<Item = &Node> {
+        self.nodes
+            .values()
+            .filter(move |node| node.has_client(client_id))
+    }
+    /// Links to or from any of the nodes owned by the client
+    pub fn client_links(&self, client_id: u32) -> Vec<u32> {
+        let port_ids = self
+            .nodes
+            .values()
+            .flat_map(|node| node.client_port_ids(client_id))
+            .collect::<HashSet<_>>();
+
+        self.links
+            .values()
+            .filter(|link| port_ids.contains(&link.from_port) || port_ids.contains(&link.to_port))
+            .map(|link| link.id)
+            .collect()
+    }
+    pub fn highlighted_client(&self) -> Option<u32> {
+        self.highlighted_client
+    }
+    pub fn highlight_client(&mut self, client_id: Option<u32>) {
+        self.highlighted_client = client_id;
+    }
+    /// Every port in the graph along with the name of its node
+    pub fn port_entries(&self) -> Vec<PortEntry> {
+        self.nodes
+            .values()
+            .flat_map(|node| {
+                node.ports().into_iter().map(move |port| PortEntry {
+                    id: port.id(),
+                    node_name: node.name().to_string(),
+                    port_name: port.name().to_string(),
+                    port_type: port.port_type(),
+                })
+            })
+            .collect()
+    }
+    pub fn find_link(&self, from_port: u32, to_port: u32) -> Option<u32> {
+        self.links
+            .values()
+            .find(|link| link.from_port == from_port && link.to_port == to_port)
+            .map(|link| link.id)
+    }
+    /// Finds a port using its `node:port` path
+    pub fn find_port_path(&self, path: &str) -> Option<u32> {
+        self.port_entries()
+            .into_iter()
+            .find(|port| port.path() == path)
+            .map(|port| port.id)
+    }
+    /// Every link along with the `node:port` paths of its ports
+    pub fn link_paths(&self) -> Vec<(u32, String, String)> {
+        let paths = self
+            .port_entries()
+            .into_iter()
+            .map(|port| (port.id, port.path()))
+            .collect::<HashMap<_, _>>();
+
+        let mut links = self
+            .links
+            .values()
+            .filter_map(|link| {
+                let from = paths.get(&link.from_port)?.clone();
+                let to = paths.get(&link.to_port)?.clone();
+                Some((link.id, from, to))
+            })
+            .collect::<Vec<_>>();
+        links.sort_by_key(|(id, _, _)| *id);
+
+        links
+    }
+    pub fn link_path(&self, id: u32) -> Option<(String, String)> {
+        self.link_paths()
+            .into_iter()
+            .find(|(link_id, _, _)| *link_id == id)
+            .map(|(_, output, input)| (output, input))
+    }
+    /// Shows a link that pipewire failed to create for a while
+    pub fn add_failed_link(&mut self, from_port: u32, to_port: u32) {
+        self.failed_links.push((from_port, to_port, Instant::now()));
+    }
+    fn failed_link_id(from_port: u32, to_port: u32) -> usize {
+        Id::new(("failed_link", from_port, to_port)).value() as usize
+    }
+    fn link_endpoint(nodes: &HashMap<Id, Node>, port_id: u32) -> Option<LinkEndpoint> {
+        nodes.values().find_map(|node| node.link_endpoint(port_id))
+    }
+    /// Checks whether two ports can be linked, returning them ordered from the output to the input
+    fn check_link(&self, port_a: u32, port_b: u32) -> Result<(u32, u32), String> {
+        let a = Self::link_endpoint(&self.nodes, port_a);
+        let b = Self::link_endpoint(&self.nodes, port_b);
+
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                a.check(&b)?;
+                match a.port_type {
+                    PortType::Input => Ok((port_b, port_a)),
+                    _ => Ok((port_a, port_b)),
+                }
+            }
+            _ => Err("Port was removed".to_string()),
+        }
+    }
+    /// Checks whether `from_port` is an output that can be linked to the input `to_port`,
+    /// links requested outside of the graph go through here
+    pub fn validate_link(&self, from_port: u32, to_port: u32) -> Result<(), String> {
+        match self.check_link(from_port, to_port)? {
+            (output, _) if output == from_port => Ok(()),
+            _ => Err("The output and input of the link are swapped".to_string()),
+        }
+    }
+    /// Ids of the pipewire nodes shown as the ui node with the given name
+    pub fn find_pw_nodes(&self, name: &str) -> Vec<u32> {
+        self.nodes
+            .get(&Id::new(name))
+            .map(|node| node.pw_node_ids())
+            .unwrap_or_default()
+    }
+    /// Selects a node as if it was selected with the keyboard and pans the canvas to it, returns false if there's no such node
+    pub fn focus_node(&mut self, name: &str) -> bool {
+        let id = Id::new(name);
+        if !self.nodes.contains_key(&id) {
+            return false;
+        }
+
+        self.focused_node = Some(id);
+        self.focused_port = None;
+
+        // The node is centred in the canvas, the same as when it is clicked on the minimap
+        let index = id.value() as usize;
+        if let (Some(canvas), Some(position)) =
+            (self.canvas, self.nodes_ctx.get_node_pos_grid_space(index))
+        {
+            let size = self
+                .nodes_ctx
+                .get_node_dimensions(index)
+                .unwrap_or_default();
+            let center = egui::Rect::from_min_size(position, size).center();
+            self.set_panning(Self::centered_panning(center, canvas.size()));
+        }
+        true
+    }
+    /// `node:port` path of the port selected with the keyboard
+    pub fn focused_port_path(&self) -> Option<String> {
+        let node = self.nodes.get(&self.focused_node?)?;
+        let port = node
+            .ports()
+            .into_iter()
+            .find(|port| Some(port.id()) == self.focused_port)?;
+
+        Some(format!("{}:{}", node.name(), port.name()))
+    }
+    /// Moves the keyboard focus between nodes with the up and down arrows,
+    /// and between the ports of the focused node with the left and right arrows
+    pub fn handle_keyboard(&mut self, ctx: &egui::CtxRef) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+
+        let input = ctx.input();
+        let step = |forward, backward| {
+            if input.key_pressed(forward) {
+                1
+            } else if input.key_pressed(backward) {
+                -1
+            } else {
+                0
+            }
+        };
+        // Steps through a list of ids, wrapping around at both ends
+        fn cycle<T: PartialEq + Copy>(ids: &[T], current: Option<T>, step: isize) -> Option<T> {
+            let next = match current.and_then(|current| ids.iter().position(|&id| id == current)) {
+                Some(ix) => (ix as isize + step).rem_euclid(ids.len() as isize) as usize,
+                None => 0,
+            };
+            ids.get(next).copied()
+        }
+
+        let node_step = step(egui::Key::ArrowDown, egui::Key::ArrowUp);
+        if node_step != 0 {
+            let mut nodes = self.nodes.values().collect::<Vec<_>>();
+            nodes.sort_by(|a, b| a.name().cmp(b.name()));
+            let ids = nodes.iter().map(|node| node.id()).collect::<Vec<_>>();
+
+            self.focused_node = cycle(&ids, self.focused_node, node_step);
+            self.focused_port = None;
+        }
+
+        let port_step = step(egui::Key::ArrowRight, egui::Key::ArrowLeft);
+        if port_step != 0 {
+            if let Some(node) = self.focused_node.and_then(|id| self.nodes.get(&id)) {
+                let ids = node
+                    .ports()
+                    .iter()
+                    .map(|port| port.id())
+                    .collect::<Vec<_>>();
+
+                self.focused_port = cycle(&ids, self.focused_port, port_step);
+            }
+        }
+
+        if input.key_pressed(egui::Key::Escape) {
+            self.focused_node = None;
+            self.focused_port = None;
+        }
+    }
+    /// Outlines the node selected with the keyboard
+    fn draw_focus(&self, ui: &egui::Ui, theme: &Theme) {
+        let id = match self.focused_node {
+            Some(id) => id.value() as usize,
+            None => return,
+        };
+
+        if let (Some(position), Some(size)) = (
+            self.nodes_ctx.get_node_pos_screen_space(id),
+            self.nodes_ctx.get_node_dimensions(id),
+        ) {
+            ui.painter().rect_stroke(
+                egui::Rect::from_min_size(position, size).expand(4.0),
+                4.0,
+                egui::Stroke::new(2.0, theme.titlebar_hovered),
+            );
+        }
+    }
     #[allow(dead_code)]
     fn get_link(&self, id: u32) -> Option<&Link> {
         self.links.get(&id)
@@ -145,61 +573,612 @@ impl Graph {
     fn get_link_mut(&mut self, id: u32) -> Option<&mut Link> {
         self.links.get_mut(&id)
     }
-    fn topo_sort_(
-        node_id: Id,
-        visited: &mut HashSet<Id>,
-        adj_list: &HashMap<Id, HashSet<Id>>,
-        stack: &mut Vec<Id>,
-    ) {
-        visited.insert(node_id);
+    fn matches_filter(&self, node: &Node) -> bool {
+        let name = node.name().to_lowercase();
+        let mut terms = self
+            .node_filter
+            .split(',')
+            .map(|term| term.trim().to_lowercase())
+            .filter(|term| !term.is_empty())
+            .peekable();
 
-        for node_id in &adj_list[&node_id] {
-            if !visited.contains(node_id) {
-                Self::topo_sort_(*node_id, visited, adj_list, stack);
-            }
+        terms.peek().is_none() || terms.any(|term| name.contains(&term))
+    }
+    pub fn set_node_filter(&mut self, filter: &str) {
+        self.node_filter = filter.to_string();
+    }
+    /// Grid space positions of the nodes keyed by their names, including those of the last layout which aren't present
+    pub fn node_positions(&self) -> HashMap<String, egui::Pos2> {
+        let mut positions = self.saved_positions.clone();
+        positions.extend(self.nodes.values().filter_map(|node| {
+            let position = self
+                .nodes_ctx
+                .get_node_pos_grid_space(node.id().value() as usize)?;
+            Some((node.name().to_string(), position))
+        }));
+
+        positions
+    }
+    /// Moves nodes to the given positions, nodes without one are arranged automatically
+    pub fn set_layout(&mut self, positions: HashMap<String, egui::Pos2>, panning: egui::Vec2) {
+        self.saved_positions = positions;
+        for node in self.nodes.values_mut() {
+            node.position = None;
         }
+        self.set_panning(panning);
+    }
+    /// Panning which puts a grid space position in the middle of a canvas of the given size
+    fn centered_panning(center: egui::Pos2, canvas_size: egui::Vec2) -> egui::Vec2 {
+        canvas_size / 2.0 - center.to_vec2()
+    }
+    pub fn panning(&self) -> egui::Vec2 {
+        self.panning
+    }
+    fn set_panning(&mut self, panning: egui::Vec2) {
+        self.panning = panning;
+        self.nodes_ctx.reset_panniing(panning);
+    }
+    /// Panning of the canvas drawn in the last frame, measured from any of the visible nodes which have been laid out
+    fn measure_panning(&self) -> Option<egui::Vec2> {
+        let origin = self.canvas?.min;
 
-        stack.push(node_id);
+        self.visible_nodes.iter().find_map(|id| {
+            let id = id.value() as usize;
+            Some(
+                self.nodes_ctx.get_node_pos_screen_space(id)?
+                    - self.nodes_ctx.get_node_pos_grid_space(id)?
+                    - origin.to_vec2(),
+            )
+        })
     }
-    //TODO: Handle stack overflows
-    fn top_sort(&self) -> Vec<Id> {
+    /// Tarjan's algorithm, returns the strongly connected components in reverse topological order.
+    /// Components with more than one node, or a single node linked to itself, are feedback loops
+    fn strongly_connected(adj_list: &HashMap<Id, HashSet<Id>>) -> Vec<Vec<Id>> {
+        let mut next_index = 0;
+        let mut index = HashMap::new();
+        let mut low_link = HashMap::new();
         let mut stack = Vec::new();
+        let mut on_stack = HashSet::new();
+        let mut components = Vec::new();
 
-        let mut visited = HashSet::new();
+        let neighbours = |node_id: Id| {
+            adj_list
+                .get(&node_id)
+                .map(|adj| adj.iter().copied().collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
 
-        let adj_list = self
-            .nodes
+        for &root in adj_list.keys() {
+            if index.contains_key(&root) {
+                continue;
+            }
+
+            // The recursion is done with an explicit stack of nodes along with the neighbours left to visit,
+            // so that long chains of nodes can't overflow the stack
+            let mut frames = vec![(root, neighbours(root))];
+            index.insert(root, next_index);
+            low_link.insert(root, next_index);
+            next_index += 1;
+            stack.push(root);
+            on_stack.insert(root);
+
+            while let Some((node_id, remaining)) = frames.last_mut() {
+                let node_id = *node_id;
+
+                match remaining.pop() {
+                    Some(next) if !index.contains_key(&next) => {
+                        index.insert(next, next_index);
+                        low_link.insert(next, next_index);
+                        next_index += 1;
+                        stack.push(next);
+                        on_stack.insert(next);
+
+                        frames.push((next, neighbours(next)));
+                    }
+                    Some(next) => {
+                        if on_stack.contains(&next) {
+                            let low = low_link[&node_id].min(index[&next]);
+                            low_link.insert(node_id, low);
+                        }
+                    }
+                    None => {
+                        frames.pop();
+
+                        if let Some((parent, _)) = frames.last() {
+                            let low = low_link[parent].min(low_link[&node_id]);
+                            low_link.insert(*parent, low);
+                        }
+
+                        if low_link[&node_id] == index[&node_id] {
+                            let mut component = Vec::new();
+                            while let Some(member) = stack.pop() {
+                                on_stack.remove(&member);
+                                component.push(member);
+                                if member == node_id {
+                                    break;
+                                }
+                            }
+                            components.push(component);
+                        }
+                    }
+                }
+            }
+        }
+
+        components
+    }
+    /// Groups of nodes which feed back into each other
+    fn feedback_loops(&self) -> Vec<Vec<Id>> {
+        let adj_list = self.adjacency(false);
+
+        Self::strongly_connected(&adj_list)
+            .into_iter()
+            .filter(|component| match component.as_slice() {
+                [node_id] => adj_list[node_id].contains(node_id),
+                _ => true,
+            })
+            .collect()
+    }
+    /// Whether linking the two ports would close a feedback loop
+    pub fn would_loop(&self, from_port: u32, to_port: u32) -> bool {
+        let port_node = |port_id| {
+            self.nodes
+                .values()
+                .find(|node| node.link_endpoint(port_id).is_some())
+                .map(|node| node.id())
+        };
+
+        // A node linked to itself, like a sink's monitor to its own playback, is a loop as well
+        match (port_node(from_port), port_node(to_port)) {
+            (Some(from_node), Some(to_node)) => {
+                Self::reachable(&self.adjacency(false), to_node).contains(&from_node)
+            }
+            _ => false,
+        }
+    }
+    /// Nodes each node links to, if `reverse` is set the nodes linking to each node instead
+    fn adjacency(&self, reverse: bool) -> HashMap<Id, HashSet<Id>> {
+        self.nodes
             .values()
             .map(|node| {
                 let adj = self
                     .links
                     .values()
-                    .filter(|link| !link.is_self_link())
-                    .filter(|link| link.from_node == node.id())
-                    .map(|link| link.to_node)
+                    .filter_map(|link| match reverse {
+                        false if link.from_node == node.id() => Some(link.to_node),
+                        true if link.to_node == node.id() => Some(link.from_node),
+                        _ => None,
+                    })
                     .collect::<HashSet<Id>>();
                 (node.id(), adj)
             })
-            .collect::<HashMap<Id, _>>();
+            .collect()
+    }
+    /// Nodes reachable from `start`, including itself
+    fn reachable(adj_list: &HashMap<Id, HashSet<Id>>, start: Id) -> HashSet<Id> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![start];
 
+        while let Some(node_id) = stack.pop() {
+            if visited.insert(node_id) {
+                stack.extend(adj_list.get(&node_id).into_iter().flatten());
+            }
+        }
+
+        visited
+    }
+    fn find_signal_path(
+        &self,
+        hovered_node: Option<usize>,
+        hovered_link: Option<usize>,
+    ) -> Option<SignalPath> {
+        // A link traces what is upstream of its output and downstream of its input
+        let (from, to) = match (hovered_link, hovered_node) {
+            (Some(link_id), _) => {
+                let link = self.links.get(&(link_id as u32))?;
+                (link.from_node, link.to_node)
+            }
+            (None, Some(node_id)) => {
+                let node = self
+                    .nodes
+                    .keys()
+                    .find(|id| id.value() as usize == node_id)?;
+                (*node, *node)
+            }
+            (None, None) => return None,
+        };
+
+        let upstream = Self::reachable(&self.adjacency(true), from);
+        let downstream = Self::reachable(&self.adjacency(false), to);
+
+        let links = self
+            .links
+            .values()
+            .filter(|link| {
+                let in_upstream =
+                    upstream.contains(&link.from_node) && upstream.contains(&link.to_node);
+                let in_downstream =
+                    downstream.contains(&link.from_node) && downstream.contains(&link.to_node);
+                let crossing =
+                    upstream.contains(&link.from_node) && downstream.contains(&link.to_node);
+
+                in_upstream || in_downstream || crossing
+            })
+            .map(|link| link.id)
+            .collect();
+
+        Some(SignalPath {
+            nodes: upstream.union(&downstream).copied().collect(),
+            links,
+        })
+    }
+    /// Nodes in topological order, the nodes of a feedback loop are next to each other in no particular order
+    fn top_sort(&self) -> Vec<Id> {
+        Self::strongly_connected(&self.adjacency(false))
+            .into_iter()
+            .rev()
+            .flatten()
+            .collect()
+    }
+    /// Outlines the nodes of each feedback loop
+    fn draw_feedback_loops(&self, ui: &egui::Ui, theme: &Theme) {
+        for feedback_loop in self.feedback_loops() {
+            let rects = feedback_loop
+                .iter()
+                .filter(|id| self.visible_nodes.contains(id))
+                .filter_map(|id| {
+                    let id = id.value() as usize;
+                    let position = self.nodes_ctx.get_node_pos_screen_space(id)?;
+                    let size = self.nodes_ctx.get_node_dimensions(id)?;
+
+                    Some(egui::Rect::from_min_size(position, size))
+                })
+                .collect::<Vec<_>>();
+
+            for rect in &rects {
+                ui.painter().rect_stroke(
+                    rect.expand(6.0),
+                    6.0,
+                    egui::Stroke::new(2.0, theme.feedback_loop),
+                );
+            }
+
+            if let Some(rect) = rects.into_iter().reduce(|a, b| a.union(b)) {
+                ui.painter().text(
+                    rect.left_top() - egui::vec2(0.0, 10.0),
+                    egui::Align2::LEFT_BOTTOM,
+                    "⚠ Feedback loop",
+                    egui::TextStyle::Body,
+                    theme.feedback_loop,
+                );
+            }
+        }
+    }
+    /// Outlines the nodes that are driven by the same driver
+    /// Visible nodes grouped by the driver they are scheduled by, only groups with more than one of them
+    fn driver_groups(&self) -> Vec<HashSet<Id>> {
+        let mut groups = HashMap::<u32, HashSet<Id>>::new();
         for node in self.nodes.values() {
-            if !visited.contains(&node.id()) {
-                Self::topo_sort_(node.id(), &mut visited, &adj_list, &mut stack)
+            if !self.visible_nodes.contains(&node.id()) {
+                continue;
+            }
+            for driver_id in node.driver_ids() {
+                groups.entry(driver_id).or_default().insert(node.id());
+            }
+        }
+
+        groups
+            .into_values()
+            .filter(|members| members.len() > 1)
+            .collect()
+    }
+    fn draw_driver_groups(&self, ui: &egui::Ui, theme: &Theme) {
+        for members in self.driver_groups() {
+            let rect = members
+                .iter()
+                .filter_map(|id| {
+                    let id = id.value() as usize;
+                    let position = self.nodes_ctx.get_node_pos_screen_space(id)?;
+                    let size = self.nodes_ctx.get_node_dimensions(id)?;
+
+                    Some(egui::Rect::from_min_size(position, size))
+                })
+                .reduce(|a, b| a.union(b));
+
+            if let Some(rect) = rect {
+                ui.painter().rect_stroke(
+                    rect.expand(12.0),
+                    8.0,
+                    egui::Stroke::new(2.0, theme.driver_group),
+                );
+            }
+        }
+    }
+    /// Links made by pw-viz are marked, depending on whether they outlive it
+    /// Links that aren't on the hovered signal path are dimmed
+    fn link_color(
+        link: &Link,
+        theme: &Theme,
+        default: egui::Color32,
+        signal_path: &Option<SignalPath>,
+    ) -> egui::Color32 {
+        let color = match link.properties {
+            LinkProperties { created: false, .. } => default,
+            LinkProperties { linger: true, .. } => theme.created_link,
+            LinkProperties { linger: false, .. } => theme.session_link,
+        };
+
+        match signal_path {
+            Some(path) if !path.links.contains(&link.id) => dim(color),
+            _ => color,
+        }
+    }
+    /// Links going from one node to another, grouped by the pair of nodes if there are several
+    fn link_bundles(&self) -> Vec<((Id, Id), Vec<u32>)> {
+        let mut bundles = HashMap::<_, Vec<_>>::new();
+        for link in self.links.values().filter(|link| !link.is_self_link()) {
+            bundles
+                .entry((link.from_node, link.to_node))
+                .or_default()
+                .push(link.id);
+        }
+
+        bundles
+            .into_iter()
+            .filter(|(_, links)| links.len() > 1)
+            .collect()
+    }
+    /// Paths of the links which egui_nodes can't draw itself, bundles have a single path for all of their links
+    fn custom_link_paths(
+        &self,
+        view: &ViewOptions,
+        pin_rows: &HashMap<usize, f32>,
+        bus_pins: &HashMap<u32, usize>,
+        bundles: &[((Id, Id), Vec<u32>)],
+        custom_links: &HashSet<u32>,
+    ) -> Vec<(Vec<u32>, Vec<egui::Pos2>)> {
+        let pin_pos = |node: Id, port: u32, output: bool| {
+            let id = node.value() as usize;
+            let rect = egui::Rect::from_min_size(
+                self.nodes_ctx.get_node_pos_screen_space(id)?,
+                self.nodes_ctx.get_node_dimensions(id)?,
+            );
+            let pin = bus_pins.get(&port).copied().unwrap_or(port as usize);
+            let x = if output { rect.max.x } else { rect.min.x };
+
+            Some(egui::pos2(x, *pin_rows.get(&pin)?))
+        };
+        let link_ends = |link: &Link| {
+            Some((
+                pin_pos(link.from_node, link.from_port, true)?,
+                pin_pos(link.to_node, link.to_port, false)?,
+            ))
+        };
+
+        let bundled = bundles
+            .iter()
+            .flat_map(|(_, links)| links.iter().copied())
+            .collect::<HashSet<_>>();
+
+        let mut paths = custom_links
+            .iter()
+            .filter(|id| !bundled.contains(id))
+            .filter_map(|id| self.links.get(id))
+            .filter_map(|link| {
+                let (from, to) = link_ends(link)?;
+                Some((vec![link.id], view.link_style.path(from, to)))
+            })
+            .collect::<Vec<_>>();
+
+        for (_, links) in bundles {
+            let ends = links
+                .iter()
+                .filter_map(|id| self.links.get(id))
+                .filter_map(link_ends)
+                .collect::<Vec<_>>();
+            if ends.is_empty() {
+                continue;
             }
+
+            // The bundle runs between the middle of the linked pins
+            let count = ends.len() as f32;
+            let (from, to) = ends.iter().fold(
+                (egui::Vec2::ZERO, egui::Vec2::ZERO),
+                |(from, to), (link_from, link_to)| {
+                    (from + link_from.to_vec2(), to + link_to.to_vec2())
+                },
+            );
+            let path = view
+                .link_style
+                .path((from / count).to_pos2(), (to / count).to_pos2());
+
+            paths.push((links.clone(), path));
         }
 
-        stack.reverse();
+        paths
+    }
+    /// Draws the links which egui_nodes can't draw itself, they are still passed to it invisibly so that they can be detached from their pins
+    fn draw_custom_links(&self, ui: &egui::Ui, theme: &Theme) {
+        let style = &self.nodes_ctx.style;
+        let painter = ui.painter();
+
+        for (links, path) in &self.custom_paths {
+            let link = match self.links.get(&links[0]) {
+                Some(link) => link,
+                None => continue,
+            };
+            let color = if self.selected_custom_links.contains(&link.id) {
+                style.colors[egui_nodes::ColorStyle::LinkSelected as usize]
+            } else if self.hovered_custom_links.contains(&link.id) {
+                style.colors[egui_nodes::ColorStyle::LinkHovered as usize]
+            } else {
+                Self::link_color(
+                    link,
+                    theme,
+                    style.colors[egui_nodes::ColorStyle::Link as usize],
+                    &self.signal_path,
+                )
+            };
+
+            if links.len() == 1 {
+                painter.add(egui::Shape::line(
+                    path.clone(),
+                    egui::Stroke::new(style.link_thickness, color),
+                ));
+                continue;
+            }
 
-        stack
+            let count = links.len() as f32;
+            let center = path_midpoint(path);
+
+            painter.add(egui::Shape::line(
+                path.clone(),
+                egui::Stroke::new(style.link_thickness * (1.0 + count.sqrt()), color),
+            ));
+            painter.circle(
+                center,
+                9.0,
+                theme.node_background,
+                egui::Stroke::new(1.5, color),
+            );
+            painter.text(
+                center,
+                egui::Align2::CENTER_CENTER,
+                links.len(),
+                egui::TextStyle::Small,
+                theme.text_color,
+            );
+        }
+    }
+    /// Draws all nodes and links in miniature in the bottom right corner of `area`,
+    /// clicking or dragging the minimap pans the canvas drawn in `canvas`
+    fn draw_minimap(&mut self, ui: &egui::Ui, canvas: egui::Rect, area: egui::Rect, theme: &Theme) {
+        let node_rects = self
+            .nodes
+            .keys()
+            .filter(|id| self.visible_nodes.contains(id))
+            .filter_map(|&id| {
+                let position = self
+                    .nodes_ctx
+                    .get_node_pos_grid_space(id.value() as usize)?;
+                let size = self.nodes_ctx.get_node_dimensions(id.value() as usize)?;
+
+                Some((id, egui::Rect::from_min_size(position, size)))
+            })
+            .collect::<HashMap<_, _>>();
+
+        if node_rects.is_empty() {
+            return;
+        }
+
+        // Screen space is grid space moved by the origin of the canvas and the panning
+        let offset = canvas.min.to_vec2() + self.panning;
+
+        let viewport = canvas.translate(-offset);
+        let bounds = node_rects
+            .values()
+            .fold(viewport, |bounds, rect| bounds.union(*rect))
+            .expand(50.0);
+
+        let map = egui::Rect::from_min_size(
+            area.max - MINIMAP_SIZE - egui::vec2(MINIMAP_MARGIN, MINIMAP_MARGIN),
+            MINIMAP_SIZE,
+        );
+        let scale = (map.width() / bounds.width()).min(map.height() / bounds.height());
+        let to_map = |pos: egui::Pos2| map.min + (pos - bounds.min) * scale;
+        let to_map_rect =
+            |rect: egui::Rect| egui::Rect::from_min_max(to_map(rect.min), to_map(rect.max));
+
+        // The minimap is on a layer above the canvas, so that clicking it doesn't also select or drag what is below
+        egui::Area::new("minimap")
+            .order(egui::Order::Foreground)
+            .fixed_pos(map.min)
+            .show(ui.ctx(), |ui| {
+                let (response, painter) =
+                    ui.allocate_painter(map.size(), egui::Sense::click_and_drag());
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    // Center the viewport on the pointer
+                    let center = bounds.min + (pointer - map.min) / scale;
+                    self.set_panning(Self::centered_panning(center, canvas.size()));
+                }
+
+                painter.rect_filled(map, 4.0, theme.node_background.linear_multiply(0.9));
+
+                for link in self.links.values() {
+                    if let (Some(from), Some(to)) = (
+                        node_rects.get(&link.from_node),
+                        node_rects.get(&link.to_node),
+                    ) {
+                        painter.line_segment(
+                            [
+                                to_map(egui::pos2(from.max.x, from.center().y)),
+                                to_map(egui::pos2(to.min.x, to.center().y)),
+                            ],
+                            egui::Stroke::new(1.0, theme.text_color.linear_multiply(0.5)),
+                        );
+                    }
+                }
+                for rect in node_rects.values() {
+                    painter.rect_filled(to_map_rect(*rect), 1.0, theme.titlebar);
+                }
+
+                painter.rect_stroke(
+                    to_map_rect(viewport),
+                    1.0,
+                    egui::Stroke::new(1.5, theme.titlebar_hovered),
+                );
+                painter.rect_stroke(map, 4.0, egui::Stroke::new(1.0, theme.titlebar));
+            });
     }
     pub fn draw<'graph, 'ui>(
         &'graph mut self,
         ctx: &'ui egui::CtxRef,
         ui: &'ui mut egui::Ui,
         theme: &'ui Theme,
+        port_labels: &'ui PortLabels,
+        port_filters: &'ui PortFilters,
+        node_lists: &'ui NodeLists,
+        profile: &'ui Profile,
+        view: &'ui ViewOptions,
     ) -> Option<LinkUpdate> {
         // Ctrl is used to trigger the debug view
         let debug_view = ctx.input().modifiers.ctrl;
+
+        let linked_ports = self
+            .links
+            .values()
+            .flat_map(|link| [link.from_port, link.to_port])
+            .collect::<HashSet<_>>();
+        let pin_rows = RefCell::new(HashMap::new());
+
+        let draw_ctx = DrawContext {
+            theme,
+            port_labels,
+            port_filters,
+            node_lists,
+            linked_ports: &linked_ports,
+            profile,
+            focused_port: self.focused_port,
+            link_source: self
+                .link_source
+                .and_then(|port_id| Some((port_id, Self::link_endpoint(&self.nodes, port_id)?))),
+            pin_rows: &pin_rows,
+            debug_view,
+        };
+
+        // Hovered nodes show all of their ports
+        let hovered_node = self.nodes_ctx.node_hovered();
+        let hovered_link = match self.hovered_custom_links.first() {
+            Some(&link_id) => Some(link_id as usize),
+            None => self
+                .nodes_ctx
+                .link_hovered()
+                .filter(|&link_id| !self.is_custom_link(link_id as u32)),
+        };
+        self.signal_path = self.find_signal_path(hovered_node, hovered_link);
+        // Ports that have been folded into a bus pin, along with the id of that bus pin
+        let mut bus_pins = HashMap::new();
+
         let mut ui_nodes = Vec::with_capacity(self.nodes.len());
 
         self.nodes_ctx.style.colors[egui_nodes::ColorStyle::NodeBackground as usize] =
@@ -208,11 +1187,45 @@ impl Graph {
             theme.node_background_hovered;
         self.nodes_ctx.style.colors[egui_nodes::ColorStyle::NodeBackgroundSelected as usize] =
             theme.node_background_hovered;
+        // Straight links are drawn by egui_nodes as curves without any bend
+        self.nodes_ctx.style.link_bezier_offset_coefficient = match view.link_style {
+            LinkStyle::Bezier => egui::vec2(0.50, 0.0),
+            LinkStyle::Orthogonal | LinkStyle::Straight => egui::Vec2::ZERO,
+        };
+
+        // Parallel links are bundled, except for those of expanded nodes so that they can be edited
+        let expanded_nodes = [
+            hovered_node.map(|id| id as u64),
+            self.focused_node.map(|id| id.value()),
+        ];
+        let bundles = if view.bundle_links {
+            self.link_bundles()
+                .into_iter()
+                .filter(|((from_node, to_node), _)| {
+                    !expanded_nodes.contains(&Some(from_node.value()))
+                        && !expanded_nodes.contains(&Some(to_node.value()))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let custom_links = match view.link_style {
+            LinkStyle::Orthogonal => self.links.keys().copied().collect(),
+            _ => bundles
+                .iter()
+                .flat_map(|(_, links)| links.iter().copied())
+                .collect::<HashSet<_>>(),
+        };
 
         ui.vertical_centered(|ui| {
             if ui.button("Arrange").clicked() {
                 log::debug!("Relayouting");
-                for node in self.nodes.values_mut() {
+                self.saved_positions.clear();
+                for node in self
+                    .nodes
+                    .values_mut()
+                    .filter(|node| !node_lists.is_pinned(node.name()))
+                {
                     node.position = None;
                 }
 
@@ -220,72 +1233,236 @@ impl Graph {
             }
         });
 
-        for node in self.nodes.values() {
+        let visible = self
+            .nodes
+            .values()
+            .filter(|node| self.matches_filter(node) && node_lists.is_shown(node.name()))
+            .map(|node| node.id())
+            .collect::<HashSet<_>>();
+
+        for node in self
+            .nodes
+            .values()
+            .filter(|node| visible.contains(&node.id()))
+        {
+            let highlighted = self
+                .highlighted_client
+                .map_or(false, |client_id| node.has_client(client_id));
+            let titlebar = if highlighted {
+                theme.highlight
+            } else {
+                theme.titlebar
+            };
+            // Hidden nodes are dimmed while they are shown anyway
+            let titlebar = match &self.signal_path {
+                Some(path) if !path.nodes.contains(&node.id()) => dim(titlebar),
+                _ if node_lists.is_hidden(node.name()) => dim(titlebar),
+                _ => titlebar,
+            };
+
             let mut ui_node = NodeConstructor::new(
                 node.id().value() as usize,
                 NodeArgs {
-                    titlebar: Some(theme.titlebar),
+                    titlebar: Some(titlebar),
                     titlebar_hovered: Some(theme.titlebar_hovered),
                     titlebar_selected: Some(theme.titlebar_hovered),
                     ..Default::default()
                 },
             );
 
-            node.draw(&mut ui_node, theme, debug_view);
+            // The focused node is expanded too, so that all of its ports can be selected
+            let expanded = hovered_node == Some(node.id().value() as usize)
+                || self.focused_node == Some(node.id());
+            bus_pins.extend(node.draw(&mut ui_node, draw_ctx, expanded));
 
             ui_nodes.push(ui_node);
         }
 
-        let links = self.links.values().map(|link| {
+        let pin_id = |port: u32| bus_pins.get(&port).copied().unwrap_or(port as usize);
+
+        self.failed_links
+            .retain(|&(_, _, failed)| super::still_shown(ctx, failed, FAILED_LINK_DURATION));
+
+        let failed_links = self.failed_links.iter().map(|&(from_port, to_port, _)| {
             (
-                link.id as usize,
-                link.from_port as usize,
-                link.to_port as usize,
-                LinkArgs::default(),
+                Self::failed_link_id(from_port, to_port),
+                pin_id(from_port),
+                pin_id(to_port),
+                LinkArgs {
+                    base: Some(egui::Color32::RED),
+                    hovered: Some(egui::Color32::LIGHT_RED),
+                    selected: Some(egui::Color32::LIGHT_RED),
+                    ..Default::default()
+                },
             )
         });
 
+        let default_link_color = self.nodes_ctx.style.colors[egui_nodes::ColorStyle::Link as usize];
+        let signal_path = &self.signal_path;
+
+        let links = self
+            .links
+            .values()
+            .filter(|link| visible.contains(&link.from_node) && visible.contains(&link.to_node))
+            .map(|link| {
+                let args = if custom_links.contains(&link.id) {
+                    LinkArgs {
+                        base: Some(egui::Color32::TRANSPARENT),
+                        hovered: Some(egui::Color32::TRANSPARENT),
+                        selected: Some(egui::Color32::TRANSPARENT),
+                        ..Default::default()
+                    }
+                } else {
+                    LinkArgs {
+                        base: Some(Self::link_color(
+                            link,
+                            theme,
+                            default_link_color,
+                            signal_path,
+                        )),
+                        ..Default::default()
+                    }
+                };
+
+                (
+                    link.id as usize,
+                    pin_id(link.from_port),
+                    pin_id(link.to_port),
+                    args,
+                )
+            })
+            .chain(failed_links);
+
+        let canvas = ui.available_rect_before_wrap();
+        self.canvas = Some(canvas);
         self.nodes_ctx.show(ui_nodes, links, ui);
+        self.visible_nodes = visible;
+        if let Some(panning) = self.measure_panning() {
+            self.panning = panning;
+        }
+        if let Some(pin) = self.nodes_ctx.link_started() {
+            self.link_source = Some(pin as u32);
+        }
+        if !ctx.input().pointer.primary_down() {
+            self.link_source = None;
+        }
+
+        self.custom_paths =
+            self.custom_link_paths(view, &pin_rows.borrow(), &bus_pins, &bundles, &custom_links);
+        self.hovered_custom_links = match ctx.input().pointer.hover_pos() {
+            Some(pointer) if hovered_node.is_none() && ui.rect_contains_pointer(canvas) => {
+                self.custom_links_at(pointer)
+            }
+            _ => Vec::new(),
+        };
+        if ctx.input().pointer.primary_clicked() && ui.rect_contains_pointer(canvas) {
+            self.selected_custom_links = self.hovered_custom_links.clone();
+        }
+        self.draw_custom_links(ui, theme);
+
+        self.draw_driver_groups(ui, theme);
+        self.draw_feedback_loops(ui, theme);
+        self.draw_focus(ui, theme);
         egui::TopBottomPanel::bottom("control_hints").show_inside(ui, |ui| {
             ui.horizontal(|ui| {
                 ui.label("[MMB] Move canvas");
                 ui.label("[LMB] Move node");
                 ui.label("[LMB] Connect port");
+                ui.label("[SHIFT]+[LMB] Connect port, toggling whether it's kept");
                 ui.label("[ALT]+[LMB] Disconnect port");
             })
         });
+        if view.show_minimap {
+            self.draw_minimap(ui, canvas, ui.available_rect_before_wrap(), theme);
+        }
 
         let mut prev_pos = egui::pos2(ui.available_width() / 4.0, ui.available_height() / 2.0);
         let mut padding = egui::pos2(75.0, 150.0);
 
         //Find the topologically sorted order of nodes in the graph
         //Nodes are currently laid out based on this order
-        let order = self.top_sort();
+        //Only the nodes that were drawn have a position in egui_nodes, the others are placed once they are shown
+        let order = self
+            .top_sort()
+            .into_iter()
+            .filter(|node_id| self.visible_nodes.contains(node_id))
+            .collect::<Vec<_>>();
+
+        // Pinned nodes stay where they are, so the nodes being arranged have to go around them
+        let pinned_rects = self
+            .nodes
+            .values()
+            .filter(|node| node.position.is_some() && node_lists.is_pinned(node.name()))
+            .filter(|node| self.visible_nodes.contains(&node.id()))
+            .filter_map(|node| {
+                let id = node.id().value() as usize;
+                Some(egui::Rect::from_min_size(
+                    self.nodes_ctx.get_node_pos_grid_space(id)?,
+                    self.nodes_ctx.get_node_dimensions(id)?,
+                ))
+            })
+            .collect::<Vec<_>>();
+
         for node_id in order {
             let node = self.nodes.get_mut(&node_id).unwrap();
 
             if !node.position.is_some() {
-                padding.y *= -1.0;
-                let node_position = egui::pos2(prev_pos.x + padding.x, prev_pos.y + padding.y);
+                let node_position = match self.saved_positions.get(node.name()) {
+                    Some(position) => *position,
+                    None => {
+                        padding.y *= -1.0;
+                        let mut position =
+                            egui::pos2(prev_pos.x + padding.x, prev_pos.y + padding.y);
+
+                        let size = self
+                            .nodes_ctx
+                            .get_node_dimensions(node_id.value() as usize)
+                            .unwrap_or(egui::vec2(padding.x, padding.x));
+                        while let Some(pinned) = pinned_rects.iter().find(|pinned| {
+                            pinned.intersects(egui::Rect::from_min_size(position, size))
+                        }) {
+                            position.x = pinned.right() + padding.x;
+                        }
+
+                        position
+                    }
+                };
 
                 node.position = Some(node_position);
                 self.nodes_ctx
                     .set_node_pos_grid_space(node_id.value() as usize, node_position);
 
                 prev_pos = node_position;
-            } else {
-                prev_pos = self
-                    .nodes_ctx
-                    .get_node_pos_grid_space(node_id.value() as usize)
-                    .unwrap();
+            } else if let Some(position) = self
+                .nodes_ctx
+                .get_node_pos_grid_space(node_id.value() as usize)
+            {
+                prev_pos = position;
             }
         }
 
         if let Some(link) = self.nodes_ctx.link_destroyed() {
-            Some(LinkUpdate::Removed(link as u32))
+            let failed_links = self.failed_links.len();
+            self.failed_links.retain(|&(from_port, to_port, _)| {
+                Self::failed_link_id(from_port, to_port) != link
+            });
+
+            // Failed links only exist in the ui
+            if self.failed_links.len() != failed_links {
+                None
+            } else {
+                Some(LinkUpdate::Removed(link as u32))
+            }
         } else if let Some((from_port, from_node, to_port, to_node, _)) =
             self.nodes_ctx.link_created_node()
         {
+            // Bus pins don't correspond to any pipewire port
+            let is_bus_pin = |pin| bus_pins.values().any(|&bus_pin| bus_pin == pin);
+            if is_bus_pin(from_port) || is_bus_pin(to_port) {
+                log::debug!("Ignoring link created from a bus pin");
+                return None;
+            }
+
             log::debug!(
                 "Created new link:\nfrom_port {}, to_port {}, from_node {}, to_node {}",
                 from_port,
@@ -294,14 +1471,234 @@ impl Graph {
                 to_node
             );
 
-            Some(LinkUpdate::Created {
-                from_port: from_port as u32,
-                to_port: to_port as u32,
-                from_node: from_node as u32,
-                to_node: to_node as u32,
-            })
+            // Links can be dragged in either direction
+            match self.check_link(from_port as u32, to_port as u32) {
+                Ok((output, input)) => {
+                    let (from_node, to_node) = if output == from_port as u32 {
+                        (from_node, to_node)
+                    } else {
+                        (to_node, from_node)
+                    };
+
+                    Some(LinkUpdate::Created {
+                        from_port: output,
+                        to_port: input,
+                        from_node: from_node as u32,
+                        to_node: to_node as u32,
+                    })
+                }
+                Err(reason) => Some(LinkUpdate::Invalid(reason)),
+            }
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adjacency list of the given edges between named nodes
+    fn adjacency(edges: &[(&str, &str)]) -> HashMap<Id, HashSet<Id>> {
+        let mut adj_list = HashMap::<Id, HashSet<Id>>::new();
+        for (from, to) in edges {
+            adj_list.entry(Id::new(to)).or_default();
+            adj_list
+                .entry(Id::new(from))
+                .or_default()
+                .insert(Id::new(to));
+        }
+        adj_list
+    }
+
+    fn components(edges: &[(&str, &str)]) -> Vec<HashSet<Id>> {
+        Graph::strongly_connected(&adjacency(edges))
+            .into_iter()
+            .map(|component| component.into_iter().collect())
+            .collect()
+    }
+
+    fn set(names: &[&str]) -> HashSet<Id> {
+        names.iter().map(Id::new).collect()
+    }
+
+    #[test]
+    fn chain_is_in_reverse_topological_order() {
+        assert_eq!(
+            components(&[("a", "b"), ("b", "c")]),
+            vec![set(&["c"]), set(&["b"]), set(&["a"])]
+        );
+    }
+
+    #[test]
+    fn two_node_cycle_is_one_component() {
+        assert_eq!(
+            components(&[("a", "b"), ("b", "a")]),
+            vec![set(&["a", "b"])]
+        );
+    }
+
+    #[test]
+    fn nested_cycles_are_merged() {
+        // a <-> b is nested in a -> b -> c -> a, which feeds d <-> e
+        let edges = [
+            ("a", "b"),
+            ("b", "a"),
+            ("b", "c"),
+            ("c", "a"),
+            ("c", "d"),
+            ("d", "e"),
+            ("e", "d"),
+        ];
+
+        assert_eq!(
+            components(&edges),
+            vec![set(&["d", "e"]), set(&["a", "b", "c"])]
+        );
+    }
+
+    #[test]
+    fn self_link_is_its_own_component() {
+        assert_eq!(
+            components(&[("a", "a"), ("a", "b")]),
+            vec![set(&["b"]), set(&["a"])]
+        );
+    }
+
+    fn properties(name: &str) -> NodeProperties {
+        NodeProperties {
+            name: name.to_string(),
+            description: None,
+            media_type: None,
+            media_name: None,
+            icon_name: None,
+            client_id: None,
+            driver_id: None,
+        }
+    }
+
+    fn add_node(graph: &mut Graph, id: u32, name: &str, client_id: Option<u32>) {
+        graph.add_node(
+            id,
+            NodeProperties {
+                client_id,
+                ..properties(name)
+            },
+        );
+    }
+
+    fn add_port(graph: &mut Graph, node_name: &str, node_id: u32, id: u32, port_type: PortType) {
+        let properties = PortProperties {
+            name: format!("port_{}", id),
+            alias: None,
+            channel: None,
+            index: None,
+            monitor: false,
+            port_type,
+            format: None,
+        };
+        graph.add_port(node_name.to_string(), node_id, Port::new(id, properties));
+    }
+
+    /// Firefox(client 30) -> Speakers(client 10), Microphone(client 10) -> Recorder(client 31)
+    fn client_graph() -> Graph {
+        let mut graph = Graph::new();
+        add_node(&mut graph, 87, "Firefox", Some(30));
+        add_node(&mut graph, 40, "Speakers", Some(10));
+        add_node(&mut graph, 41, "Microphone", Some(10));
+        add_node(&mut graph, 42, "Recorder", Some(31));
+        add_port(&mut graph, "Firefox", 87, 100, PortType::Output);
+        add_port(&mut graph, "Speakers", 40, 200, PortType::Input);
+        add_port(&mut graph, "Microphone", 41, 201, PortType::Output);
+        add_port(&mut graph, "Recorder", 42, 202, PortType::Input);
+
+        let link = |graph: &mut Graph, id, from: &str, to: &str, from_port, to_port| {
+            graph.add_link(
+                id,
+                from.to_string(),
+                to.to_string(),
+                from_port,
+                to_port,
+                LinkProperties::default(),
+            )
+        };
+        link(&mut graph, 300, "Firefox", "Speakers", 100, 200);
+        link(&mut graph, 301, "Microphone", "Recorder", 201, 202);
+
+        graph
+    }
+
+    #[test]
+    fn client_links_table() {
+        let graph = client_graph();
+
+        for (client_id, expected) in [
+            (30, vec![300]),
+            (10, vec![300, 301]),
+            (31, vec![301]),
+            (99, vec![]),
+        ] {
+            let mut links = graph.client_links(client_id);
+            links.sort_unstable();
+            assert_eq!(links, expected, "links of client {}", client_id);
+        }
+    }
+
+    #[test]
+    fn client_nodes_are_the_nodes_it_owns() {
+        let graph = client_graph();
+
+        let mut names = graph
+            .client_nodes(10)
+            .map(|node| node.name())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, ["Microphone", "Speakers"]);
+
+        assert_eq!(graph.client_nodes(99).count(), 0);
+    }
+
+    #[test]
+    fn centered_panning_moves_the_center_to_the_middle_of_the_canvas() {
+        let canvas = egui::Rect::from_min_size(egui::pos2(0.0, 30.0), egui::vec2(800.0, 600.0));
+        let node = egui::Rect::from_min_size(egui::pos2(100.0, 50.0), egui::vec2(80.0, 40.0));
+
+        let panning = Graph::centered_panning(node.center(), canvas.size());
+        assert_eq!(panning, egui::vec2(260.0, 230.0));
+
+        // Screen space is grid space moved by the origin of the canvas and the panning
+        assert_eq!(
+            node.center() + canvas.min.to_vec2() + panning,
+            canvas.center()
+        );
+    }
+
+    #[test]
+    fn driver_groups_only_contain_visible_nodes() {
+        let mut graph = Graph::new();
+        for (id, name, driver_id) in [
+            (1, "Speakers", 1),
+            (2, "Firefox", 1),
+            (3, "Hidden", 1),
+            (4, "Microphone", 4),
+            (5, "Recorder", 4),
+            (6, "Headphones", 6),
+        ] {
+            graph.add_node(
+                id,
+                NodeProperties {
+                    driver_id: Some(driver_id),
+                    ..properties(name)
+                },
+            );
+        }
+        graph.visible_nodes = ["Speakers", "Firefox", "Microphone", "Headphones"]
+            .iter()
+            .map(Id::new)
+            .collect();
+
+        // Hidden and Recorder aren't visible, which leaves Microphone alone in its group
+        assert_eq!(graph.driver_groups(), vec![set(&["Speakers", "Firefox"])]);
+    }
+}
@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pipewire_impl::LinkProperties;
+
 use super::Id;
 
 #[derive(Debug)]
@@ -9,6 +13,7 @@ pub struct Link {
     pub from_port: u32,
     pub to_port: u32,
     pub active: bool,
+    pub properties: LinkProperties,
 }
 
 impl Link {
@@ -16,3 +21,101 @@ impl Link {
         self.from_node == self.to_node
     }
 }
+
+/// How links are routed between ports
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LinkStyle {
+    Bezier,
+    Orthogonal,
+    Straight,
+}
+
+impl LinkStyle {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LinkStyle::Bezier => "Curved",
+            LinkStyle::Orthogonal => "Orthogonal",
+            LinkStyle::Straight => "Straight",
+        }
+    }
+    /// Points of the path from an output pin at `from` to an input pin at `to`
+    pub fn path(&self, from: egui::Pos2, to: egui::Pos2) -> Vec<egui::Pos2> {
+        // Distance the path keeps going straight out of a pin
+        const STUB: f32 = 20.0;
+
+        match self {
+            LinkStyle::Straight => vec![from, to],
+            LinkStyle::Orthogonal if to.x - from.x >= 2.0 * STUB => {
+                let x = (from.x + to.x) / 2.0;
+                vec![from, egui::pos2(x, from.y), egui::pos2(x, to.y), to]
+            }
+            // Links going backwards are routed around the nodes through the space between them
+            LinkStyle::Orthogonal => {
+                let y = (from.y + to.y) / 2.0;
+                vec![
+                    from,
+                    egui::pos2(from.x + STUB, from.y),
+                    egui::pos2(from.x + STUB, y),
+                    egui::pos2(to.x - STUB, y),
+                    egui::pos2(to.x - STUB, to.y),
+                    to,
+                ]
+            }
+            LinkStyle::Bezier => {
+                const SEGMENTS: usize = 24;
+
+                let offset = egui::vec2(((to.x - from.x).abs() * 0.5).max(STUB), 0.0);
+                let (p0, p1, p2, p3) = (from, from + offset, to - offset, to);
+
+                (0..=SEGMENTS)
+                    .map(|ix| {
+                        let t = ix as f32 / SEGMENTS as f32;
+                        let u = 1.0 - t;
+
+                        (p0.to_vec2() * (u * u * u)
+                            + p1.to_vec2() * (3.0 * u * u * t)
+                            + p2.to_vec2() * (3.0 * u * t * t)
+                            + p3.to_vec2() * (t * t * t))
+                            .to_pos2()
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Point halfway along a path
+pub fn path_midpoint(path: &[egui::Pos2]) -> egui::Pos2 {
+    let length = path
+        .windows(2)
+        .map(|segment| segment[0].distance(segment[1]))
+        .sum::<f32>();
+
+    let mut remaining = length / 2.0;
+    for segment in path.windows(2) {
+        let segment_length = segment[0].distance(segment[1]);
+        if remaining <= segment_length && segment_length > 0.0 {
+            return segment[0] + (segment[1] - segment[0]) * (remaining / segment_length);
+        }
+        remaining -= segment_length;
+    }
+
+    path.first().copied().unwrap_or(egui::Pos2::ZERO)
+}
+
+/// Shortest distance from `point` to any segment of a path
+pub fn path_distance(path: &[egui::Pos2], point: egui::Pos2) -> f32 {
+    path.windows(2)
+        .map(|segment| {
+            let (a, b) = (segment[0], segment[1]);
+            let (ab, ap) = (b - a, point - a);
+            // Where the point projects onto the segment, from 0 at `a` to 1 at `b`
+            let t = if ab.length_sq() > 0.0 {
+                ((ap.x * ab.x + ap.y * ab.y) / ab.length_sq()).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            point.distance(a + ab * t)
+        })
+        .fold(f32::INFINITY, f32::min)
+}
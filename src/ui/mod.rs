@@ -2,29 +2,78 @@ mod graph;
 mod id;
 mod link;
 mod node;
+mod palette;
 mod port;
+mod workspace;
 
-use crate::pipewire_impl::PipewireMessage;
+use crate::{
+    ipc::{IpcError, IpcRequest},
+    pipewire_impl::{ClientProperties, LinkOptions, PipewireMessage, PortType, Profile},
+    script::{Script, ScriptAction},
+};
 use eframe::epi;
-use pipewire::channel::Sender;
+use pipewire::{channel::Sender, permissions::PermissionFlags};
 use serde::{Deserialize, Serialize};
-use std::sync::mpsc::Receiver;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
+};
 
-use graph::Graph;
+use graph::{Graph, ViewOptions};
 use id::Id;
-use port::Port;
+use link::LinkStyle;
+use node::NodeLists;
+use palette::{CommandPalette, PaletteCommand};
+use port::{Port, PortFilters, PortLabels};
+use workspace::{WorkspaceAction, Workspaces};
 
 pub const INITIAL_WIDTH: u32 = 1280;
 pub const INITIAL_HEIGHT: u32 = 720;
 
+const QUANTUMS: &[u32] = &[32, 64, 128, 256, 512, 1024, 2048, 4096, 8192];
+const RATES: &[u32] = &[44100, 48000, 88200, 96000, 176400, 192000];
+
+/// How long notifications stay on screen
+const NOTIFICATION_DURATION: Duration = Duration::from_secs(4);
+
 #[derive(Debug)]
 pub enum UiMessage {
     RemoveLink(u32),
-    AddLink { from_port: u32, to_port: u32 },
+    AddLink {
+        from_port: u32,
+        to_port: u32,
+        options: LinkOptions,
+    },
+    /// Saves the graph to a config fragment, along with the port labels keyed by `<node>:<port>` paths
+    ExportConfig {
+        path: PathBuf,
+        port_labels: HashMap<String, String>,
+    },
+    ImportConfig(PathBuf),
+    /// 0 stops forcing the quantum
+    ForceQuantum(u32),
+    /// 0 stops forcing the rate
+    ForceRate(u32),
+    /// Asks for the permissions a client has, which are sent back as `ClientPermissions`
+    GetClientPermissions(u32),
+    /// Sets the permissions of a client on the given objects
+    UpdateClientPermissions {
+        client_id: u32,
+        permissions: Vec<(u32, PermissionFlags)>,
+    },
+    /// Sets the software volume of a node, 1.0 being unity gain
+    SetVolume {
+        node_id: u32,
+        volume: f32,
+    },
     Exit,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Theme {
     titlebar: egui::Color32,
     titlebar_hovered: egui::Color32,
@@ -42,6 +91,15 @@ pub struct Theme {
 
     node_background: egui::Color32,
     node_background_hovered: egui::Color32,
+
+    driver_group: egui::Color32,
+    highlight: egui::Color32,
+
+    /// Links created by pw-viz which are kept after it exits
+    created_link: egui::Color32,
+    /// Links created by pw-viz which are destroyed when it exits
+    session_link: egui::Color32,
+    feedback_loop: egui::Color32,
 }
 
 impl Default for Theme {
@@ -62,6 +120,13 @@ impl Default for Theme {
             text_color: egui::Color32::WHITE,
             node_background: egui::Color32::from_rgba_unmultiplied(50, 50, 50, 255),
             node_background_hovered: egui::Color32::from_rgba_unmultiplied(75, 75, 75, 255),
+
+            driver_group: egui::Color32::from_rgba_unmultiplied(255, 255, 255, 40),
+            highlight: egui::Color32::from_rgba_unmultiplied(214, 140, 46, 255),
+
+            created_link: egui::Color32::from_rgba_unmultiplied(120, 170, 235, 255),
+            session_link: egui::Color32::from_rgba_unmultiplied(235, 200, 90, 255),
+            feedback_loop: egui::Color32::from_rgba_unmultiplied(230, 60, 60, 255),
         }
     }
 }
@@ -70,10 +135,41 @@ pub struct GraphUI {
     graph: Graph,
     pipewire_receiver: Receiver<PipewireMessage>,
     pipewire_sender: Sender<UiMessage>,
+    /// Requests from the control socket, if it could be opened
+    ipc_receiver: Option<Receiver<IpcRequest>>,
+    script: Script,
     theme: Theme,
+    port_labels: PortLabels,
+    port_filters: PortFilters,
+    node_lists: NodeLists,
+    palette: CommandPalette,
+    workspaces: Workspaces,
+    /// Options new links are created with
+    link_options: LinkOptions,
+    /// Link being edited in the link window, along with the options it will be recreated with
+    link_edit: Option<(u32, LinkOptions)>,
+    /// Link which would close a feedback loop, waiting to be confirmed
+    feedback_link: Option<(u32, u32, LinkOptions)>,
+    profile: Profile,
+    /// `clock.*` properties of the settings metadata
+    settings: HashMap<String, String>,
+    clients: BTreeMap<u32, ClientProperties>,
+    /// Permissions of a client on other objects, keyed by object id
+    client_permissions: HashMap<u32, BTreeMap<u32, PermissionFlags>>,
+    /// Client whose permissions are being edited
+    permissions_client: Option<u32>,
+    /// Object id entered to add a new permission
+    new_permission_object: u32,
     show_theme: bool,
     show_about: bool,
     show_controls: bool,
+    show_config: bool,
+    show_clock: bool,
+    show_clients: bool,
+    view: ViewOptions,
+    config_path: String,
+    /// Message shown at the bottom of the graph, along with when it was shown
+    notification: Option<(String, Instant)>,
 }
 
 impl GraphUI {
@@ -85,10 +181,34 @@ impl GraphUI {
             graph: Graph::new(),
             pipewire_receiver,
             pipewire_sender,
+            ipc_receiver: None,
+            script: Script::new(),
             theme: Theme::default(),
+            port_labels: PortLabels::default(),
+            port_filters: PortFilters::default(),
+            node_lists: NodeLists::default(),
+            palette: CommandPalette::default(),
+            workspaces: Workspaces::default(),
+            link_options: LinkOptions::default(),
+            link_edit: None,
+            feedback_link: None,
+            profile: Profile::default(),
+            settings: HashMap::new(),
+            clients: BTreeMap::new(),
+            client_permissions: HashMap::new(),
+            permissions_client: None,
+            new_permission_object: 0,
             show_theme: false,
             show_about: false,
             show_controls: false,
+            show_config: false,
+            show_clock: false,
+            show_clients: false,
+            view: ViewOptions::default(),
+            config_path: crate::pipewire_impl::default_config_path()
+                .display()
+                .to_string(),
+            notification: None,
         }
     }
 
@@ -142,6 +262,26 @@ impl GraphUI {
                     ui.label("Text color");
                     ui.color_edit_button_srgba(&mut theme.text_color);
                     ui.end_row();
+
+                    ui.label("Driver group");
+                    ui.color_edit_button_srgba(&mut theme.driver_group);
+                    ui.end_row();
+
+                    ui.label("Highlighted client");
+                    ui.color_edit_button_srgba(&mut theme.highlight);
+                    ui.end_row();
+
+                    ui.label("Link created by pw-viz");
+                    ui.color_edit_button_srgba(&mut theme.created_link);
+                    ui.end_row();
+
+                    ui.label("Link destroyed on exit");
+                    ui.color_edit_button_srgba(&mut theme.session_link);
+                    ui.end_row();
+
+                    ui.label("Feedback loop");
+                    ui.color_edit_button_srgba(&mut theme.feedback_loop);
+                    ui.end_row();
                 });
 
                 if ui.button("Default").clicked() {
@@ -194,35 +334,169 @@ impl GraphUI {
                     ui.label("Ctrl");
                     ui.label("Show pipewire ids of nodes and ports");
                     ui.end_row();
+
+                    ui.label("Ctrl + K");
+                    ui.label("Open the command palette, e.g. node:port -> node:port");
+                    ui.end_row();
+
+                    ui.label("Up / Down");
+                    ui.label("Select a node");
+                    ui.end_row();
+
+                    ui.label("Left / Right");
+                    ui.label("Select a port of the selected node");
+                    ui.end_row();
+
+                    ui.label("Enter");
+                    ui.label("Link the selected port using the command palette");
+                    ui.end_row();
                 })
             });
     }
 
+    fn config_window(&mut self, ctx: &egui::CtxRef, _ui: &mut egui::Ui) {
+        let config_path = &mut self.config_path;
+        let pipewire_sender = &self.pipewire_sender;
+        let port_labels = &self.port_labels;
+        egui::Window::new("PipeWire config")
+            .open(&mut self.show_config)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Virtual devices, loopbacks, links and port labels are saved as a pipewire.conf.d fragment");
+
+                ui.horizontal(|ui| {
+                    ui.label("Path");
+                    ui.text_edit_singleline(config_path);
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.button("Export").clicked() {
+                        pipewire_sender
+                            .send(UiMessage::ExportConfig {
+                                path: PathBuf::from(&*config_path),
+                                port_labels: port_labels.labels().clone(),
+                            })
+                            .expect("Failed to send ui message");
+                    }
+                    if ui.button("Import").clicked() {
+                        pipewire_sender
+                            .send(UiMessage::ImportConfig(PathBuf::from(&*config_path)))
+                            .expect("Failed to send ui message");
+                    }
+                });
+            });
+    }
+
+    fn clock_window(&mut self, ctx: &egui::CtxRef, _ui: &mut egui::Ui) {
+        let settings = &self.settings;
+        let pipewire_sender = &self.pipewire_sender;
+
+        let setting = |key: &str| {
+            settings
+                .get(key)
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or_default()
+        };
+
+        egui::Window::new("Clock")
+            .open(&mut self.show_clock)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("clock_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("Quantum");
+                    ui.label(setting("clock.quantum").to_string());
+                    ui.end_row();
+
+                    ui.label("Rate");
+                    ui.label(setting("clock.rate").to_string());
+                    ui.end_row();
+
+                    let mut force_quantum = setting("clock.force-quantum");
+                    ui.label("Force quantum");
+                    egui::ComboBox::from_id_source("force_quantum")
+                        .selected_text(match force_quantum {
+                            0 => "Default".to_string(),
+                            quantum => quantum.to_string(),
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut force_quantum, 0, "Default");
+                            for &quantum in QUANTUMS {
+                                ui.selectable_value(
+                                    &mut force_quantum,
+                                    quantum,
+                                    quantum.to_string(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    if force_quantum != setting("clock.force-quantum") {
+                        pipewire_sender
+                            .send(UiMessage::ForceQuantum(force_quantum))
+                            .expect("Failed to send ui message");
+                    }
+
+                    let mut force_rate = setting("clock.force-rate");
+                    ui.label("Force rate");
+                    egui::ComboBox::from_id_source("force_rate")
+                        .selected_text(match force_rate {
+                            0 => "Default".to_string(),
+                            rate => rate.to_string(),
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut force_rate, 0, "Default");
+                            for &rate in RATES {
+                                ui.selectable_value(&mut force_rate, rate, rate.to_string());
+                            }
+                        });
+                    ui.end_row();
+
+                    if force_rate != setting("clock.force-rate") {
+                        pipewire_sender
+                            .send(UiMessage::ForceRate(force_rate))
+                            .expect("Failed to send ui message");
+                    }
+                });
+            });
+    }
+
     /// Update the graph ui based on the message sent by the pipewire thread
     fn process_message(&mut self, message: PipewireMessage) {
         match message {
-            PipewireMessage::NodeAdded {
-                id,
-                name,
-                description,
-                media_type,
-            } => {
-                self.graph.add_node(name, id, description, media_type);
+            PipewireMessage::NodeAdded { id, properties } => {
+                let name = properties.name.clone();
+                self.graph.add_node(id, properties);
+                self.script.on_node_added(&name);
+            }
+            PipewireMessage::NodeStateChanged { id, state } => {
+                self.graph.set_node_state(id, state);
             }
             PipewireMessage::NodeRemoved { name, id } => {
                 self.graph.remove_node(&name, id);
+                self.script.on_node_removed(&name);
             }
 
             PipewireMessage::PortAdded {
                 node_name,
                 node_id,
                 id,
-                name,
-                port_type,
+                properties,
             } => {
-                let port = Port::new(id, name, port_type);
+                let port_name = properties.name.clone();
+                let port = Port::new(id, properties);
 
-                self.graph.add_port(node_name, node_id, port);
+                self.graph.add_port(node_name.clone(), node_id, port);
+                self.script.on_port_added(&node_name, &port_name);
+            }
+            PipewireMessage::NodeChanged { id, properties } => {
+                self.graph.change_node(id, properties);
+            }
+            PipewireMessage::PortChanged {
+                node_id,
+                id,
+                properties,
+            } => {
+                self.graph.change_port(node_id, id, properties);
             }
 
             PipewireMessage::LinkAdded {
@@ -231,9 +505,20 @@ impl GraphUI {
                 to_node_name,
                 from_port,
                 to_port,
+                properties,
             } => {
-                self.graph
-                    .add_link(id, from_node_name, to_node_name, from_port, to_port);
+                self.graph.add_link(
+                    id,
+                    from_node_name,
+                    to_node_name,
+                    from_port,
+                    to_port,
+                    properties,
+                );
+
+                if let Some((output, input)) = self.graph.link_path(id) {
+                    self.script.on_link_added(&output, &input);
+                }
             }
             PipewireMessage::PortRemoved {
                 node_name,
@@ -243,10 +528,762 @@ impl GraphUI {
                 self.graph.remove_port(&node_name, node_id, id);
             }
             PipewireMessage::LinkRemoved { id } => {
+                // The paths have to be looked up before the link is gone
+                let paths = self.graph.link_path(id);
                 self.graph.remove_link(id);
+
+                if let Some((output, input)) = paths {
+                    self.script.on_link_removed(&output, &input);
+                }
+            }
+            PipewireMessage::LinkChanged { id, properties } => {
+                self.graph.change_link(id, properties);
             }
             PipewireMessage::LinkStateChanged { id: _, active: _ } => {}
+            PipewireMessage::LinkFailed {
+                from_port,
+                to_port,
+                error,
+            } => {
+                self.graph.add_failed_link(from_port, to_port);
+                self.notify(format!("Failed to create link: {}", error));
+            }
+            PipewireMessage::PortLabelsImported(labels) => self.port_labels.extend(labels),
+            PipewireMessage::Error(error) => self.notify(error),
+            PipewireMessage::ClientAdded { id, properties }
+            | PipewireMessage::ClientChanged { id, properties } => {
+                self.clients.insert(id, properties);
+            }
+            PipewireMessage::ClientPermissions {
+                id,
+                index,
+                permissions,
+            } => {
+                let client_permissions = self.client_permissions.entry(id).or_default();
+                // The whole list is sent starting at index 0
+                if index == 0 {
+                    client_permissions.clear();
+                }
+                client_permissions.extend(permissions);
+            }
+            PipewireMessage::ClientRemoved { id } => {
+                self.clients.remove(&id);
+                self.client_permissions.remove(&id);
+                if self.permissions_client == Some(id) {
+                    self.permissions_client = None;
+                }
+                if self.graph.highlighted_client() == Some(id) {
+                    self.graph.highlight_client(None);
+                }
+            }
+            PipewireMessage::Profile(profile) => {
+                self.profile = profile;
+            }
+            PipewireMessage::SettingChanged { key, value } => match value {
+                Some(value) => {
+                    self.settings.insert(key, value);
+                }
+                None => {
+                    self.settings.remove(&key);
+                }
+            },
+        };
+    }
+
+    /// Keeps the layout of the graph in the current workspace
+    fn store_workspace(&mut self) {
+        let workspace = self.workspaces.current_mut();
+        workspace.positions = self.graph.node_positions();
+        workspace.panning = self.graph.panning();
+    }
+    fn load_workspace(&mut self) {
+        let workspace = self.workspaces.current();
+        self.graph
+            .set_layout(workspace.positions.clone(), workspace.panning);
+    }
+
+    fn workspace_tabs(&mut self, ctx: &egui::CtxRef) {
+        let action = egui::TopBottomPanel::top("workspace_tabs")
+            .show(ctx, |ui| self.workspaces.tabs(ui))
+            .inner;
+
+        match action {
+            Some(WorkspaceAction::Switch(index)) => {
+                self.store_workspace();
+                self.workspaces.switch(index);
+                self.load_workspace();
+            }
+            Some(WorkspaceAction::Add) => {
+                self.store_workspace();
+                let index = self.workspaces.add();
+                self.workspaces.switch(index);
+                self.load_workspace();
+            }
+            Some(WorkspaceAction::Remove(index)) => {
+                self.store_workspace();
+                self.workspaces.remove(index);
+                self.load_workspace();
+            }
+            None => {}
+        }
+
+        self.graph
+            .set_node_filter(&self.workspaces.current().filter);
+    }
+
+    /// Quantum, rate, DSP load and xruns of every driver reported by the profiler
+    fn status_bar(&self, ctx: &egui::CtxRef) {
+        if self.profile.drivers.is_empty() {
+            return;
+        }
+
+        let mut drivers = self.profile.drivers.values().collect::<Vec<_>>();
+        drivers.sort_by_key(|driver| driver.id);
+
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for driver in drivers {
+                    let name = self
+                        .graph
+                        .find_node(driver.id)
+                        .map(|node| node.name())
+                        .unwrap_or(&driver.name);
+
+                    ui.label(format!(
+                        "{}: {}/{} DSP {:.1}% xruns {}",
+                        name,
+                        driver.quantum,
+                        driver.rate,
+                        driver.cpu_load * 100.0,
+                        driver.xruns
+                    ));
+                    ui.separator();
+                }
+            });
+        });
+    }
+
+    /// Lists the clients connected to pipewire along with the nodes they own
+    fn clients_panel(&mut self, ctx: &egui::CtxRef) {
+        let graph = &mut self.graph;
+        let pipewire_sender = &self.pipewire_sender;
+        let permissions_client = &mut self.permissions_client;
+
+        egui::SidePanel::left("clients_panel").show(ctx, |ui| {
+            ui.heading("Clients");
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (&id, client) in &self.clients {
+                    let name = client
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("Client {}", id));
+
+                    egui::CollapsingHeader::new(name)
+                        .id_source(("client", id))
+                        .show(ui, |ui| {
+                            egui::Grid::new(("client_grid", id))
+                                .num_columns(2)
+                                .show(ui, |ui| {
+                                    ui.label("Id");
+                                    ui.label(id.to_string());
+                                    ui.end_row();
+
+                                    if let Some(pid) = client.pid {
+                                        ui.label("PID");
+                                        ui.label(pid.to_string());
+                                        ui.end_row();
+                                    }
+                                    if let Some(binary) = &client.binary {
+                                        ui.label("Binary");
+                                        ui.label(binary);
+                                        ui.end_row();
+                                    }
+                                    if let Some(access) = &client.access {
+                                        ui.label("Access");
+                                        ui.label(access);
+                                        ui.end_row();
+                                    }
+                                });
+
+                            for node in graph.client_nodes(id) {
+                                ui.label(format!("• {}", node.name()));
+                            }
+
+                            ui.horizontal(|ui| {
+                                let highlighted = graph.highlighted_client() == Some(id);
+                                if ui.selectable_label(highlighted, "Highlight").clicked() {
+                                    graph.highlight_client(if highlighted {
+                                        None
+                                    } else {
+                                        Some(id)
+                                    });
+                                }
+
+                                if ui.button("Permissions").clicked() {
+                                    *permissions_client = Some(id);
+                                    pipewire_sender
+                                        .send(UiMessage::GetClientPermissions(id))
+                                        .expect("Failed to send ui message");
+                                }
+
+                                let links = graph.client_links(id);
+                                if ui
+                                    .add_enabled(
+                                        !links.is_empty(),
+                                        egui::Button::new("Disconnect all"),
+                                    )
+                                    .clicked()
+                                {
+                                    for link_id in links {
+                                        pipewire_sender
+                                            .send(UiMessage::RemoveLink(link_id))
+                                            .expect("Failed to send ui message");
+                                    }
+                                }
+                            });
+                        });
+                }
+            });
+        });
+    }
+
+    fn permissions_window(&mut self, ctx: &egui::CtxRef, _ui: &mut egui::Ui) {
+        let client_id = match self.permissions_client {
+            Some(client_id) => client_id,
+            None => return,
+        };
+
+        let name = self
+            .clients
+            .get(&client_id)
+            .and_then(|client| client.name.clone())
+            .unwrap_or_else(|| format!("Client {}", client_id));
+
+        let graph = &self.graph;
+        let pipewire_sender = &self.pipewire_sender;
+        let new_permission_object = &mut self.new_permission_object;
+        let permissions = self.client_permissions.get(&client_id);
+
+        let mut updated = Vec::new();
+        let mut open = true;
+
+        egui::Window::new(format!("Permissions of {}", name))
+            .id(egui::Id::new("permissions_window"))
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Permissions of objects the client can see, changes are applied immediately",
+                );
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("permissions_grid")
+                        .num_columns(5)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Object");
+                            for flag in ["R", "W", "X", "M"] {
+                                ui.label(flag);
+                            }
+                            ui.end_row();
+
+                            for (&object_id, &flags) in permissions.into_iter().flatten() {
+                                // An id of u32::MAX holds the default permissions for all other objects
+                                let object = if object_id == u32::MAX {
+                                    "Default".to_string()
+                                } else {
+                                    match graph.find_node(object_id) {
+                                        Some(node) => format!("{} ({})", node.name(), object_id),
+                                        None => object_id.to_string(),
+                                    }
+                                };
+                                ui.label(object);
+
+                                let mut new_flags = flags;
+                                for flag in [
+                                    PermissionFlags::R,
+                                    PermissionFlags::W,
+                                    PermissionFlags::X,
+                                    PermissionFlags::M,
+                                ] {
+                                    let mut set = new_flags.contains(flag);
+                                    if ui.checkbox(&mut set, "").changed() {
+                                        new_flags.set(flag, set);
+                                    }
+                                }
+                                ui.end_row();
+
+                                if new_flags != flags {
+                                    updated.push((object_id, new_flags));
+                                }
+                            }
+                        });
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Object id");
+                    ui.add(egui::DragValue::new(new_permission_object));
+                    if let Some(node) = graph.find_node(*new_permission_object) {
+                        ui.label(node.name());
+                    }
+                    if ui.button("Deny access").clicked() {
+                        updated.push((*new_permission_object, PermissionFlags::empty()));
+                    }
+                });
+
+                if ui.button("Refresh").clicked() {
+                    pipewire_sender
+                        .send(UiMessage::GetClientPermissions(client_id))
+                        .expect("Failed to send ui message");
+                }
+            });
+
+        if !updated.is_empty() {
+            self.pipewire_sender
+                .send(UiMessage::UpdateClientPermissions {
+                    client_id,
+                    permissions: updated,
+                })
+                .expect("Failed to send ui message");
+        }
+
+        if !open {
+            self.permissions_client = None;
+        }
+    }
+
+    /// Shows the properties of the selected link, links can't be changed so they are recreated with the new options
+    fn link_window(&mut self, ctx: &egui::CtxRef, _ui: &mut egui::Ui) {
+        // A bundle of links is selected as a whole, and can only be removed as a whole
+        let bundle = self
+            .graph
+            .selected_links()
+            .iter()
+            .map(|link| link.id)
+            .collect::<Vec<_>>();
+        if bundle.len() > 1 {
+            self.link_edit = None;
+            let mut remove = false;
+
+            egui::Window::new("Links")
+                .id(egui::Id::new("link_window"))
+                .resizable(true)
+                .show(ctx, |ui| {
+                    for &link_id in &bundle {
+                        if let Some((output, input)) = self.graph.link_path(link_id) {
+                            ui.label(format!("{} -> {}", output, input));
+                        }
+                    }
+                    ui.separator();
+                    remove = ui.button("Remove links").clicked();
+                });
+
+            if remove {
+                for link_id in bundle {
+                    self.pipewire_sender
+                        .send(UiMessage::RemoveLink(link_id))
+                        .expect("Failed to send ui message");
+                }
+            }
+            return;
+        }
+
+        let link = match self.graph.selected_link() {
+            Some(link) => link,
+            None => {
+                self.link_edit = None;
+                return;
+            }
+        };
+
+        let options = match &mut self.link_edit {
+            Some((id, options)) if *id == link.id => options,
+            link_edit => &mut link_edit.insert((link.id, link.properties.options())).1,
+        };
+        let (output, input) = self
+            .graph
+            .link_path(link.id)
+            .unwrap_or_else(|| (link.from_port.to_string(), link.to_port.to_string()));
+
+        let mut recreate = false;
+
+        egui::Window::new("Link")
+            .id(egui::Id::new("link_window"))
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label(format!("{} -> {}", output, input));
+                ui.label(if link.properties.created {
+                    "Created by pw-viz"
+                } else {
+                    "Created by another client"
+                });
+                if let Some(factory_id) = link.properties.factory_id {
+                    ui.label(format!("Factory: {}", factory_id));
+                }
+                ui.separator();
+
+                // Links made by another client, like the session manager, are left alone
+                // as it may recreate or remove them itself
+                ui.add_enabled_ui(link.properties.created, |ui| {
+                    ui.checkbox(&mut options.linger, "Keep after exit")
+                        .on_hover_text("object.linger");
+                    ui.checkbox(&mut options.passive, "Passive")
+                        .on_hover_text("link.passive, the link doesn't keep its nodes running");
+                    ui.checkbox(&mut options.feedback, "Feedback")
+                        .on_hover_text("link.feedback, the link closes a loop in the graph");
+
+                    let changed = *options != link.properties.options();
+                    recreate = ui
+                        .add_enabled(changed, egui::Button::new("Recreate link"))
+                        .on_hover_text("Replaces the link with a new one with these options")
+                        .clicked();
+                })
+                .response
+                .on_disabled_hover_text("Only links created by pw-viz can be changed");
+
+                ui.separator();
+                egui::CollapsingHeader::new("Properties (read-only)").show(ui, |ui| {
+                    let mut props = link.properties.props.iter().collect::<Vec<_>>();
+                    props.sort();
+
+                    egui::Grid::new("link_props_grid")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (key, value) in props {
+                                ui.label(key);
+                                ui.label(value);
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+
+        if recreate {
+            self.pipewire_sender
+                .send(UiMessage::RemoveLink(link.id))
+                .expect("Failed to send ui message");
+            self.pipewire_sender
+                .send(UiMessage::AddLink {
+                    from_port: link.from_port,
+                    to_port: link.to_port,
+                    options: *options,
+                })
+                .expect("Failed to send ui message");
+        }
+    }
+
+    /// Creates a link, links which would close a feedback loop have to be confirmed first
+    fn request_link(&mut self, from_port: u32, to_port: u32, options: LinkOptions) {
+        if let Err(reason) = self.graph.validate_link(from_port, to_port) {
+            self.notify(reason);
+        } else if self.graph.would_loop(from_port, to_port) {
+            self.feedback_link = Some((from_port, to_port, options));
+        } else {
+            self.pipewire_sender
+                .send(UiMessage::AddLink {
+                    from_port,
+                    to_port,
+                    options,
+                })
+                .expect("Failed to send ui message");
+        }
+    }
+
+    fn feedback_window(&mut self, ctx: &egui::CtxRef, _ui: &mut egui::Ui) {
+        let (from_port, to_port, options) = match self.feedback_link {
+            Some(link) => link,
+            None => return,
+        };
+
+        let mut confirmed = None;
+
+        egui::Window::new("⚠ Feedback loop")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(
+                    "This link would feed the output of a node back into itself, \
+                     which can cause loud noise.",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Create anyway").clicked() {
+                        confirmed = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        confirmed = Some(false);
+                    }
+                });
+            });
+
+        match confirmed {
+            Some(true) => {
+                self.feedback_link = None;
+                self.pipewire_sender
+                    .send(UiMessage::AddLink {
+                        from_port,
+                        to_port,
+                        options: LinkOptions {
+                            feedback: true,
+                            ..options
+                        },
+                    })
+                    .expect("Failed to send ui message");
+            }
+            Some(false) => self.feedback_link = None,
+            None => {}
+        }
+    }
+
+    /// Opens the command palette with Ctrl+K, or with Enter to link the port selected with the keyboard
+    fn command_palette(&mut self, ctx: &egui::CtxRef) {
+        let input = ctx.input();
+        if input.modifiers.command && input.key_pressed(egui::Key::K) {
+            self.palette.open(String::new());
+        } else if !self.palette.is_open()
+            && !ctx.wants_keyboard_input()
+            && input.key_pressed(egui::Key::Enter)
+        {
+            if let Some(path) = self.graph.focused_port_path() {
+                self.palette.open(format!("{} -> ", path));
+            }
+        }
+
+        if !self.palette.is_open() {
+            self.graph.handle_keyboard(ctx);
+        }
+
+        match self.palette.show(ctx, &self.graph) {
+            Some(PaletteCommand::Connect { from_port, to_port }) => {
+                self.request_link(from_port, to_port, self.link_options);
+            }
+            Some(PaletteCommand::Disconnect(link_id)) => {
+                self.pipewire_sender
+                    .send(UiMessage::RemoveLink(link_id))
+                    .expect("Failed to send ui message");
+            }
+            None => {}
+        }
+    }
+
+    /// Answer a request received on the control socket
+    fn process_ipc_request(&mut self, request: IpcRequest) {
+        #[derive(Deserialize)]
+        struct LinkParams {
+            output: String,
+            input: String,
+            /// Override the options new links are created with
+            linger: Option<bool>,
+            passive: Option<bool>,
+            /// Links closing a feedback loop are refused unless this is set
+            feedback: Option<bool>,
+        }
+        #[derive(Deserialize)]
+        struct SessionParams {
+            path: Option<PathBuf>,
+        }
+        #[derive(Deserialize)]
+        struct NodeParams {
+            node: String,
+        }
+
+        let find_port = |graph: &Graph, path: &str| {
+            graph
+                .find_port_path(path)
+                .ok_or_else(|| IpcError::Failed(format!("Couldn't find port {}", path)))
         };
+
+        let result = match request.method.as_str() {
+            "list_ports" => {
+                let ports = self
+                    .graph
+                    .port_entries()
+                    .into_iter()
+                    .map(|port| {
+                        let direction = match port.port_type {
+                            PortType::Input => "in",
+                            PortType::Output => "out",
+                            PortType::Unknown => "unknown",
+                        };
+                        json!({ "id": port.id, "path": port.path(), "direction": direction })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(Value::from(ports))
+            }
+            "list_links" => {
+                let links = self
+                    .graph
+                    .link_paths()
+                    .into_iter()
+                    .map(
+                        |(id, output, input)| json!({ "id": id, "output": output, "input": input }),
+                    )
+                    .collect::<Vec<_>>();
+                Ok(Value::from(links))
+            }
+            "connect" | "disconnect" => request.params::<LinkParams>().and_then(|params| {
+                let from_port = find_port(&self.graph, &params.output)?;
+                let to_port = find_port(&self.graph, &params.input)?;
+
+                let message = if request.method == "connect" {
+                    self.graph
+                        .validate_link(from_port, to_port)
+                        .map_err(IpcError::Failed)?;
+
+                    let feedback = params.feedback.unwrap_or(false);
+                    if !feedback && self.graph.would_loop(from_port, to_port) {
+                        return Err(IpcError::Failed(format!(
+                            "Link {} -> {} closes a feedback loop, set \"feedback\" to create it anyway",
+                            params.output, params.input
+                        )));
+                    }
+                    UiMessage::AddLink {
+                        from_port,
+                        to_port,
+                        options: LinkOptions {
+                            linger: params.linger.unwrap_or(self.link_options.linger),
+                            passive: params.passive.unwrap_or(self.link_options.passive),
+                            feedback: feedback || self.link_options.feedback,
+                        },
+                    }
+                } else {
+                    let link_id = self.graph.find_link(from_port, to_port).ok_or_else(|| {
+                        IpcError::Failed(format!(
+                            "{} is not linked to {}",
+                            params.output, params.input
+                        ))
+                    })?;
+                    UiMessage::RemoveLink(link_id)
+                };
+
+                self.pipewire_sender
+                    .send(message)
+                    .expect("Failed to send ui message");
+                Ok(Value::Null)
+            }),
+            "load_session" => request.params::<SessionParams>().map(|params| {
+                let path = params
+                    .path
+                    .unwrap_or_else(|| PathBuf::from(&self.config_path));
+
+                self.pipewire_sender
+                    .send(UiMessage::ImportConfig(path))
+                    .expect("Failed to send ui message");
+                Value::Null
+            }),
+            "focus_node" => request.params::<NodeParams>().and_then(|params| {
+                if self.graph.focus_node(&params.node) {
+                    Ok(Value::Null)
+                } else {
+                    Err(IpcError::Failed(format!(
+                        "Couldn't find node {}",
+                        params.node
+                    )))
+                }
+            }),
+            method => Err(IpcError::MethodNotFound(format!(
+                "Unknown method {}",
+                method
+            ))),
+        };
+
+        request.reply(result);
+    }
+
+    /// Carry out the actions requested by the routing script
+    fn run_script_actions(&mut self) {
+        for action in self.script.take_actions() {
+            let message = match &action {
+                ScriptAction::Connect { output, input }
+                | ScriptAction::Disconnect { output, input } => {
+                    let ports = self
+                        .graph
+                        .find_port_path(output)
+                        .zip(self.graph.find_port_path(input));
+
+                    match (ports, &action) {
+                        (Some((from_port, to_port)), ScriptAction::Connect { .. }) => {
+                            if let Err(reason) = self.graph.validate_link(from_port, to_port) {
+                                log::error!(
+                                    "Not linking {} -> {} from the script: {}",
+                                    output,
+                                    input,
+                                    reason
+                                );
+                                continue;
+                            }
+                            // Scripts run unattended, so there is no one to confirm the link
+                            if self.graph.would_loop(from_port, to_port) {
+                                log::error!(
+                                    "Not linking {} -> {} from the script, it closes a feedback loop",
+                                    output,
+                                    input
+                                );
+                                continue;
+                            }
+                            Some(UiMessage::AddLink {
+                                from_port,
+                                to_port,
+                                options: self.link_options,
+                            })
+                        }
+                        (Some((from_port, to_port)), _) => self
+                            .graph
+                            .find_link(from_port, to_port)
+                            .map(UiMessage::RemoveLink),
+                        (None, _) => None,
+                    }
+                }
+                ScriptAction::SetVolume { node, volume } => {
+                    for node_id in self.graph.find_pw_nodes(node) {
+                        self.pipewire_sender
+                            .send(UiMessage::SetVolume {
+                                node_id,
+                                volume: *volume,
+                            })
+                            .expect("Failed to send ui message");
+                    }
+                    continue;
+                }
+            };
+
+            match message {
+                Some(message) => self
+                    .pipewire_sender
+                    .send(message)
+                    .expect("Failed to send ui message"),
+                None => log::warn!("Script action {:?} doesn't match the graph", action),
+            }
+        }
+    }
+
+    fn notify(&mut self, message: String) {
+        log::warn!("{}", message);
+        self.notification = Some((message, Instant::now()));
+    }
+
+    fn notification(&mut self, ctx: &egui::CtxRef) {
+        let message = match &self.notification {
+            Some((message, shown)) if still_shown(ctx, *shown, NOTIFICATION_DURATION) => message,
+            Some(_) => {
+                self.notification = None;
+                return;
+            }
+            None => return,
+        };
+
+        egui::Area::new("notification")
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -48.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(egui::RichText::new(message).color(egui::Color32::LIGHT_RED));
+                });
+            });
     }
 
     /// Keep processing messages in a non blocking way until there aren't any new messages
@@ -262,6 +1299,15 @@ impl GraphUI {
                 },
             }
         }
+
+        if let Some(ipc_receiver) = &self.ipc_receiver {
+            let requests = ipc_receiver.try_iter().collect::<Vec<_>>();
+            for request in requests {
+                self.process_ipc_request(request);
+            }
+        }
+
+        self.run_script_actions();
     }
 }
 
@@ -274,18 +1320,49 @@ impl epi::App for GraphUI {
     fn setup(
         &mut self,
         _ctx: &egui::CtxRef,
-        _frame: &epi::Frame,
+        frame: &epi::Frame,
         storage: Option<&dyn epi::Storage>,
     ) {
+        let (ipc_sender, ipc_receiver) = std::sync::mpsc::channel();
+        match crate::ipc::default_socket_path() {
+            Some(socket_path) => match crate::ipc::spawn(&socket_path, ipc_sender, frame.clone()) {
+                Ok(()) => self.ipc_receiver = Some(ipc_receiver),
+                Err(err) => log::error!(
+                    "Failed to open control socket {}: {}",
+                    socket_path.display(),
+                    err
+                ),
+            },
+            None => log::warn!("XDG_RUNTIME_DIR isn't set, the control socket is disabled"),
+        }
+
         if let Some(storage) = storage {
             self.theme = epi::get_value(storage, "theme").unwrap_or_default();
+            self.port_labels = epi::get_value(storage, "port_labels").unwrap_or_default();
+            self.port_filters = epi::get_value(storage, "port_filters").unwrap_or_default();
+            self.node_lists = epi::get_value(storage, "node_lists").unwrap_or_default();
+            self.link_options = epi::get_value(storage, "link_options").unwrap_or_default();
+            self.view = epi::get_value(storage, "view").unwrap_or_default();
+            self.workspaces = epi::get_value(storage, "workspaces").unwrap_or_default();
+            self.workspaces.repair();
         }
+        self.load_workspace();
+
+        self.script.load(&crate::script::default_path());
     }
 
     /// Called by the frame work to save state before shutdown.
     /// Note that you must enable the `persistence` feature for this to work.
     fn save(&mut self, storage: &mut dyn epi::Storage) {
         epi::set_value(storage, "theme", &self.theme);
+        epi::set_value(storage, "port_labels", &self.port_labels);
+        epi::set_value(storage, "port_filters", &self.port_filters);
+        epi::set_value(storage, "node_lists", &self.node_lists);
+        epi::set_value(storage, "link_options", &self.link_options);
+        epi::set_value(storage, "view", &self.view);
+
+        self.store_workspace();
+        epi::set_value(storage, "workspaces", &self.workspaces);
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
@@ -295,6 +1372,12 @@ impl epi::App for GraphUI {
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 egui::menu::menu_button(ui, "File", |ui| {
+                    if ui.button("Import/Export config").clicked() {
+                        self.show_config = true;
+                    }
+                    if ui.button("Reload script").clicked() {
+                        self.script.load(&crate::script::default_path());
+                    }
                     if ui.button("Quit").clicked() {
                         frame.quit();
                     }
@@ -303,6 +1386,26 @@ impl epi::App for GraphUI {
                     if ui.button("Theme").clicked() {
                         self.show_theme = true;
                     }
+                    if ui.button("Clock").clicked() {
+                        self.show_clock = true;
+                    }
+                    ui.checkbox(&mut self.show_clients, "Clients");
+                    ui.checkbox(&mut self.view.show_minimap, "Minimap");
+                    ui.separator();
+                    for style in [
+                        LinkStyle::Bezier,
+                        LinkStyle::Orthogonal,
+                        LinkStyle::Straight,
+                    ] {
+                        ui.radio_value(&mut self.view.link_style, style, style.label());
+                    }
+                    ui.checkbox(&mut self.view.bundle_links, "Bundle parallel links");
+                    ui.checkbox(&mut self.link_options.linger, "Keep links after exit");
+                    ui.checkbox(&mut self.link_options.passive, "Create passive links");
+                    ui.separator();
+                    self.port_filters.global.ui(ui);
+                    ui.separator();
+                    egui::menu::menu_button(ui, "Hidden nodes", |ui| self.node_lists.ui(ui));
                 });
                 egui::menu::menu_button(ui, "Help", |ui| {
                     if ui.button("Controls").clicked() {
@@ -315,9 +1418,26 @@ impl epi::App for GraphUI {
             });
         });
 
+        self.workspace_tabs(ctx);
+        self.status_bar(ctx);
+        self.command_palette(ctx);
+
+        if self.show_clients {
+            self.clients_panel(ctx);
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // If any new links were created/removed, notify the pipewire thread
-            if let Some(link_update) = self.graph.draw(ctx, ui, &self.theme) {
+            if let Some(link_update) = self.graph.draw(
+                ctx,
+                ui,
+                &self.theme,
+                &self.port_labels,
+                &self.port_filters,
+                &self.node_lists,
+                &self.profile,
+                &self.view,
+            ) {
                 match link_update {
                     graph::LinkUpdate::Created {
                         from_port,
@@ -325,15 +1445,20 @@ impl epi::App for GraphUI {
                         from_node: _,
                         to_node: _,
                     } => {
-                        self.pipewire_sender
-                            .send(UiMessage::AddLink { from_port, to_port })
-                            .expect("Failed to send ui message");
+                        // Holding shift inverts the setting for this link only
+                        let options = LinkOptions {
+                            linger: self.link_options.linger != ctx.input().modifiers.shift,
+                            ..self.link_options
+                        };
+
+                        self.request_link(from_port, to_port, options);
                     }
                     graph::LinkUpdate::Removed(link_id) => {
                         self.pipewire_sender
                             .send(UiMessage::RemoveLink(link_id))
                             .expect("Failed to send ui message");
                     }
+                    graph::LinkUpdate::Invalid(reason) => self.notify(reason),
                 }
             }
 
@@ -346,6 +1471,20 @@ impl epi::App for GraphUI {
             if self.show_controls {
                 self.controls_window(ctx, ui);
             }
+            if self.show_config {
+                self.config_window(ctx, ui);
+            }
+            if self.show_clock {
+                self.clock_window(ctx, ui);
+            }
+            if self.permissions_client.is_some() {
+                self.permissions_window(ctx, ui);
+            }
+            self.link_window(ctx, ui);
+            self.feedback_window(ctx, ui);
+
+            self.port_labels.edit_window(ctx);
+            self.notification(ctx);
         });
     }
 
@@ -353,7 +1492,23 @@ impl epi::App for GraphUI {
         self.pipewire_sender
             .send(UiMessage::Exit)
             .expect("Failed to send ui message");
+
+        if let (Some(_), Some(socket_path)) =
+            (&self.ipc_receiver, crate::ipc::default_socket_path())
+        {
+            let _ = std::fs::remove_file(socket_path);
+        }
+    }
+}
+
+/// Whether something shown since `shown` for `duration` should still be shown.
+/// Repaints are requested until then, so that it disappears on time even if nothing else happens
+fn still_shown(ctx: &egui::CtxRef, shown: Instant, duration: Duration) -> bool {
+    let visible = shown.elapsed() < duration;
+    if visible {
+        ctx.request_repaint();
     }
+    visible
 }
 
 pub fn run_graph_ui(receiver: Receiver<PipewireMessage>, sender: Sender<UiMessage>) {
@@ -1,9 +1,105 @@
 use egui_nodes::{NodeConstructor, PinArgs};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap, HashSet},
+};
 
-use crate::pipewire_impl::MediaType;
+use crate::pipewire_impl::{
+    LinkEndpoint, MediaType, NodeProperties, NodeState, PortProperties, PortType, Profile,
+};
 
-use super::{port::Port, Id, Theme};
+use super::{
+    port::{Port, PortFilters, PortLabels, PortVisibility},
+    Id, Theme,
+};
+
+/// Nodes pinned in place or hidden by the user, keyed by node name so that they survive restarts.
+/// The lists are changed from the context menu of a node while it is being drawn
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeLists {
+    /// Pinned nodes aren't moved when the graph is arranged
+    pinned: RefCell<BTreeSet<String>>,
+    hidden: RefCell<BTreeSet<String>>,
+    pub show_hidden: bool,
+}
+
+impl NodeLists {
+    pub fn is_pinned(&self, node_name: &str) -> bool {
+        self.pinned.borrow().contains(node_name)
+    }
+    pub fn is_hidden(&self, node_name: &str) -> bool {
+        self.hidden.borrow().contains(node_name)
+    }
+    /// Hidden nodes are still shown while `show_hidden` is set
+    pub fn is_shown(&self, node_name: &str) -> bool {
+        self.show_hidden || !self.is_hidden(node_name)
+    }
+    fn toggle(list: &RefCell<BTreeSet<String>>, node_name: &str) {
+        let mut list = list.borrow_mut();
+        if !list.remove(node_name) {
+            list.insert(node_name.to_string());
+        }
+    }
+    pub fn node_menu(&self, ui: &mut egui::Ui, node_name: &str) {
+        let mut pinned = self.is_pinned(node_name);
+        if ui.checkbox(&mut pinned, "Pinned").changed() {
+            Self::toggle(&self.pinned, node_name);
+        }
+
+        let label = if self.is_hidden(node_name) {
+            "Unhide"
+        } else {
+            "Hide"
+        };
+        if ui.button(label).clicked() {
+            Self::toggle(&self.hidden, node_name);
+            ui.close_menu();
+        }
+    }
+    /// Lists the hidden nodes so that they can be shown again
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.show_hidden, "Show hidden nodes");
+
+        let hidden = self.hidden.get_mut();
+        if hidden.is_empty() {
+            ui.label(egui::RichText::new("No hidden nodes").weak());
+        }
+
+        let mut unhidden = None;
+        for node_name in hidden.iter() {
+            ui.horizontal(|ui| {
+                ui.label(node_name);
+                if ui.small_button("Unhide").clicked() {
+                    unhidden = Some(node_name.clone());
+                }
+            });
+        }
+        if let Some(node_name) = unhidden {
+            hidden.remove(&node_name);
+        }
+    }
+}
+
+/// Shared by all the nodes while drawing a frame
+#[derive(Clone, Copy)]
+pub struct DrawContext<'a> {
+    pub theme: &'a Theme,
+    pub port_labels: &'a PortLabels,
+    pub port_filters: &'a PortFilters,
+    pub node_lists: &'a NodeLists,
+    /// Ports which have at least one link
+    pub linked_ports: &'a HashSet<u32>,
+    pub profile: &'a Profile,
+    /// Port selected with the keyboard
+    pub focused_port: Option<u32>,
+    /// Port a link is being dragged from, ports which can't be linked to it are greyed out
+    pub link_source: Option<(u32, LinkEndpoint<'a>)>,
+    /// Screen space height of each pin, recorded as the nodes are laid out
+    pub pin_rows: &'a RefCell<HashMap<usize, f32>>,
+    pub debug_view: bool,
+}
 
 #[derive(Debug)]
 pub struct Node {
@@ -29,19 +125,41 @@ impl Node {
         self.id
     }
 
-    pub(super) fn add_pw_node(
-        &mut self,
-        id: u32,
-        description: Option<String>,
-        media_type: Option<MediaType>,
-    ) {
+    pub(super) fn add_pw_node(&mut self, id: u32, properties: NodeProperties) {
         self.pw_nodes.push(PwNode {
             id,
-            description,
-            media_type,
+            description: properties.description,
+            media_type: properties.media_type,
+            media_name: properties.media_name,
+            icon_name: properties.icon_name,
+            client_id: properties.client_id,
+            driver_id: properties.driver_id,
+            state: None,
             ports: HashMap::new(),
         });
     }
+    pub(super) fn update_pw_node(&mut self, id: u32, properties: NodeProperties) {
+        if let Some(pw_node) = self.get_pw_node(id) {
+            pw_node.description = properties.description;
+            pw_node.media_type = properties.media_type;
+            pw_node.media_name = properties.media_name;
+            pw_node.icon_name = properties.icon_name;
+            pw_node.client_id = properties.client_id;
+            pw_node.driver_id = properties.driver_id;
+        }
+    }
+    /// Removes a pipewire node along with its ports, so that it can be moved to another ui node
+    pub(super) fn take_pw_node(&mut self, id: u32) -> Option<PwNode> {
+        let index = self.pw_nodes.iter().position(|node| node.id == id)?;
+
+        Some(self.pw_nodes.remove(index))
+    }
+    pub(super) fn insert_pw_node(&mut self, pw_node: PwNode) {
+        self.pw_nodes.push(pw_node);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pw_nodes.is_empty()
+    }
     //TODO: Use pooling
     pub(super) fn remove_pw_node(&mut self, id: u32) -> bool {
         self.pw_nodes.retain(|node| node.id != id);
@@ -64,6 +182,63 @@ impl Node {
             .ports
             .insert(port.id(), port);
     }
+    pub fn set_state(&mut self, id: u32, state: NodeState) {
+        if let Some(pw_node) = self.get_pw_node(id) {
+            pw_node.state = Some(state);
+        }
+    }
+    pub fn has_pw_node(&self, id: u32) -> bool {
+        self.pw_nodes.iter().any(|node| node.id == id)
+    }
+    pub fn link_endpoint(&self, port_id: u32) -> Option<LinkEndpoint> {
+        self.pw_nodes.iter().find_map(|pw_node| {
+            let port = pw_node.ports.get(&port_id)?;
+            Some(pw_node.link_endpoint(port))
+        })
+    }
+    pub fn pw_node_ids(&self) -> Vec<u32> {
+        self.pw_nodes.iter().map(|node| node.id).collect()
+    }
+    /// Ports of all the pipewire nodes, in the order they are drawn
+    pub fn ports(&self) -> Vec<&Port> {
+        let mut ports = Vec::new();
+        for pw_node in self.pw_nodes.iter() {
+            let mut pw_node_ports = pw_node.ports.values().collect::<Vec<_>>();
+            pw_node_ports.sort_by(|a, b| a.display_order(b));
+            ports.extend(pw_node_ports);
+        }
+        ports
+    }
+    /// Whether any of the pipewire nodes is owned by the client
+    pub fn has_client(&self, client_id: u32) -> bool {
+        self.pw_nodes
+            .iter()
+            .any(|node| node.client_id == Some(client_id))
+    }
+    /// Ids of the drivers the pipewire nodes are scheduled by
+    pub fn driver_ids(&self) -> HashSet<u32> {
+        self.pw_nodes
+            .iter()
+            .filter_map(|node| node.driver_id)
+            .collect()
+    }
+    /// Ports of the pipewire nodes owned by the client
+    pub fn client_port_ids(&self, client_id: u32) -> HashSet<u32> {
+        self.pw_nodes
+            .iter()
+            .filter(|node| node.client_id == Some(client_id))
+            .flat_map(|node| node.port_ids())
+            .collect()
+    }
+    pub fn change_port(&mut self, node_id: u32, port_id: u32, properties: PortProperties) {
+        match self
+            .get_pw_node(node_id)
+            .and_then(|pw_node| pw_node.ports.get_mut(&port_id))
+        {
+            Some(port) => port.update(properties),
+            None => log::error!("Port with id: {} was never added", port_id),
+        }
+    }
     pub fn remove_port(&mut self, node_id: u32, port_id: u32) {
         if let Some(pw_node) = self.get_pw_node(node_id) {
             pw_node.ports.remove(&port_id);
@@ -71,110 +246,210 @@ impl Node {
             log::error!("Pipewire node with id: {} was never added", node_id);
         }
     }
+    /// Pin id used for the bus pin that the hidden ports of a pipewire node are folded into
+    pub fn bus_pin_id(node_id: u32, port_type: PortType) -> usize {
+        let direction = match port_type {
+            PortType::Input => "in",
+            PortType::Output | PortType::Unknown => "out",
+        };
+        Id::new((node_id, direction)).value() as usize
+    }
+
+    /// Returns the ids of the ports that were folded into a bus pin, along with the id of that bus pin
     fn draw_ports<'graph, 'node>(
         ui_node: &'graph mut NodeConstructor<'node>,
+        node_name: &str,
         node: &'node PwNode,
-        theme: &'node Theme,
-        debug: bool,
-    ) {
-        let mut ports = node.ports.values().collect::<Vec<_>>();
+        ctx: DrawContext<'node>,
+        visibility: Option<PortVisibility>,
+    ) -> Vec<(u32, usize)> {
+        let DrawContext {
+            theme,
+            port_labels,
+            linked_ports,
+            focused_port,
+            link_source,
+            pin_rows,
+            debug_view: debug,
+            ..
+        } = ctx;
+
+        let (background, hovered) = match &node.media_type {
+            Some(MediaType::Audio) => (theme.audio_port, theme.audio_port_hovered),
+            Some(MediaType::Video) => (theme.video_port, theme.video_port_hovered),
+            Some(MediaType::Midi) => (theme.midi_port, theme.midi_port_hovered),
+            None => (egui::Color32::GRAY, egui::Color32::LIGHT_GRAY),
+        };
+        let pin_args = || PinArgs {
+            background: Some(background),
+            hovered: Some(hovered),
+            ..Default::default()
+        };
 
-        //Sorts ports based on alphabetical ordering
-        ports.sort_by(|a, b| a.name().cmp(b.name()));
+        let mut ports = Vec::new();
+        let mut hidden_inputs = Vec::new();
+        let mut hidden_outputs = Vec::new();
+
+        for port in node.ports.values() {
+            let hidden = visibility
+                .map(|visibility| visibility.is_hidden(port, linked_ports.contains(&port.id())))
+                .unwrap_or(false);
+
+            match port.port_type() {
+                PortType::Input if hidden => hidden_inputs.push(port.id()),
+                PortType::Output if hidden => hidden_outputs.push(port.id()),
+                _ => ports.push(port),
+            }
+        }
+
+        ports.sort_by(|a, b| a.display_order(b));
+
+        // Section headings are only needed if the node has ports in more than one section
+        let multiple_sections = ports
+            .windows(2)
+            .any(|pair| pair[0].section() != pair[1].section());
 
         for (ix, port) in ports.iter().enumerate() {
-            let (background, hovered) = match &node.media_type {
-                Some(MediaType::Audio) => (theme.audio_port, theme.audio_port_hovered),
-                Some(MediaType::Video) => (theme.video_port, theme.video_port_hovered),
-                Some(MediaType::Midi) => (theme.midi_port, theme.midi_port_hovered),
-                None => (egui::Color32::GRAY, egui::Color32::LIGHT_GRAY),
-            };
+            let section =
+                if multiple_sections && (ix == 0 || ports[ix - 1].section() != port.section()) {
+                    Some(port.section().label())
+                } else {
+                    None
+                };
+
+            // Custom labels take priority over the alias
+            let label = port_labels
+                .get(node_name, port.name())
+                .unwrap_or_else(|| port.display_name())
+                .to_string();
+
             let port_name = {
                 if debug {
-                    format!("{} [{}]", port.name(), port.id())
+                    format!("{} ({}) [{}]", label, port.name(), port.id())
                 } else {
-                    format!("{} ", port.name())
+                    format!("{} ", label)
                 }
             };
+            let focused = focused_port == Some(port.id());
+            let linkable = match link_source {
+                Some((source_id, source)) if source_id != port.id() => {
+                    source.check(&node.link_endpoint(port)).is_ok()
+                }
+                _ => true,
+            };
 
-            let first = debug && ix == 0;
+            // The first port also shows the description of the pipewire node in the debug view
+            let node_desc = if debug && ix == 0 {
+                let node_desc_str = if let Some(desc) = &node.description {
+                    desc
+                } else {
+                    ""
+                };
 
-            let node_desc_str = if let Some(desc) = &node.description {
-                desc
+                Some(format!("{} [{}]", node_desc_str, node.id))
             } else {
-                ""
+                None
             };
 
-            let node_desc = format!("{} [{}]", node_desc_str, node.id);
+            let node_name = node_name.to_string();
+            let port_name_key = port.name().to_string();
+            let pin = port.id() as usize;
 
-            match port.port_type() {
-                crate::pipewire_impl::PortType::Input => {
-                    if first {
-                        ui_node.with_input_attribute(
-                            port.id() as usize,
-                            PinArgs {
-                                background: Some(background),
-                                hovered: Some(hovered),
-                                ..Default::default()
-                            },
-                            move |ui| {
-                                ui.label(
-                                    egui::RichText::new(node_desc).color(egui::Color32::WHITE),
-                                );
-                                ui.label(port_name)
-                            },
-                        );
-                    } else {
-                        ui_node.with_input_attribute(
-                            port.id() as usize,
-                            PinArgs {
-                                background: Some(background),
-                                hovered: Some(hovered),
-                                ..Default::default()
-                            },
-                            |ui| ui.label(port_name),
-                        );
-                    }
+            let add_contents = move |ui: &mut egui::Ui| {
+                if let Some(node_desc) = node_desc {
+                    ui.label(egui::RichText::new(node_desc).color(egui::Color32::WHITE));
                 }
-                crate::pipewire_impl::PortType::Output => {
-                    if first {
-                        ui_node.with_output_attribute(
-                            port.id() as usize,
-                            PinArgs {
-                                background: Some(background),
-                                hovered: Some(hovered),
-                                ..Default::default()
-                            },
-                            move |ui| {
-                                ui.label(
-                                    egui::RichText::new(node_desc).color(egui::Color32::WHITE),
-                                );
-                                ui.label(port_name)
-                            },
-                        );
-                    } else {
-                        ui_node.with_output_attribute(
-                            port.id() as usize,
-                            PinArgs {
-                                background: Some(background),
-                                hovered: Some(hovered),
-                                ..Default::default()
-                            },
-                            |ui| ui.label(port_name),
-                        );
+                if let Some(section) = section {
+                    ui.label(egui::RichText::new(section).weak().small());
+                }
+                let port_name = if focused {
+                    egui::RichText::new(format!("▶ {}", port_name)).strong()
+                } else if !linkable {
+                    egui::RichText::new(port_name).weak()
+                } else {
+                    egui::RichText::new(port_name)
+                };
+                let response = ui.label(port_name).context_menu(|ui| {
+                    if ui.button("Rename").clicked() {
+                        port_labels.request_edit(&node_name, &port_name_key, &label);
+                        ui.close_menu();
                     }
+                });
+                pin_rows.borrow_mut().insert(pin, response.rect.center().y);
+
+                response
+            };
+
+            // The focused pin is drawn as if it was hovered
+            let args = if focused {
+                PinArgs {
+                    background: Some(hovered),
+                    ..pin_args()
+                }
+            } else if !linkable {
+                PinArgs {
+                    background: Some(egui::Color32::from_gray(70)),
+                    hovered: Some(egui::Color32::from_gray(70)),
+                    ..pin_args()
+                }
+            } else {
+                pin_args()
+            };
+
+            match port.port_type() {
+                PortType::Input => {
+                    ui_node.with_input_attribute(port.id() as usize, args, add_contents);
+                }
+                PortType::Output => {
+                    ui_node.with_output_attribute(port.id() as usize, args, add_contents);
+                }
+                PortType::Unknown => {}
+            }
+        }
+
+        let mut folded = Vec::new();
+
+        for (port_type, hidden) in [
+            (PortType::Input, hidden_inputs),
+            (PortType::Output, hidden_outputs),
+        ] {
+            if hidden.is_empty() {
+                continue;
+            }
+
+            let bus_pin = Self::bus_pin_id(node.id, port_type);
+            let label = format!("⋯ {} ports", hidden.len());
+            let add_contents = move |ui: &mut egui::Ui| {
+                let response = ui.label(egui::RichText::new(label).weak());
+                pin_rows
+                    .borrow_mut()
+                    .insert(bus_pin, response.rect.center().y);
+
+                response
+            };
+
+            match port_type {
+                PortType::Input => {
+                    ui_node.with_input_attribute(bus_pin, pin_args(), add_contents);
+                }
+                _ => {
+                    ui_node.with_output_attribute(bus_pin, pin_args(), add_contents);
                 }
-                crate::pipewire_impl::PortType::Unknown => {}
             }
+
+            folded.extend(hidden.into_iter().map(|port_id| (port_id, bus_pin)));
         }
+
+        folded
     }
 
+    /// Hidden ports are only folded into bus pins while the node is not expanded
     pub fn draw<'graph, 'node>(
         &'node self,
         ui_node: &'graph mut NodeConstructor<'node>,
-        theme: &'node Theme,
-        debug_view: bool,
-    ) {
+        ctx: DrawContext<'node>,
+        expanded: bool,
+    ) -> Vec<(u32, usize)> {
         // let media_type = node.media_type;
         // let media_emoji = match media_type {
         //     Some(MediaType::Audio) => "🔉",
@@ -196,26 +471,285 @@ impl Node {
             }
         }
 
+        // Processing load and xruns reported by the profiler, shown after the title
+        let mut load = None;
+        let mut xruns = 0;
+        let mut timings = Vec::new();
+        for node in self.pw_nodes.iter() {
+            if let Some(profile) = ctx.profile.nodes.get(&node.id) {
+                load = Some(load.unwrap_or(0.0f32).max(profile.load));
+                // The block of a driver reports the same xruns as the driver itself
+                if !ctx.profile.drivers.contains_key(&node.id) {
+                    xruns += profile.xruns.unwrap_or_default();
+                }
+                timings.push(format!(
+                    "[{}] driver {} wait {:.1}µs busy {:.1}µs",
+                    node.id,
+                    profile.driver_id,
+                    profile.wait * 1e6,
+                    profile.busy * 1e6
+                ));
+            }
+            if let Some(driver) = ctx.profile.drivers.get(&node.id) {
+                xruns += driver.xruns;
+            }
+        }
+
+        let is_driver = self
+            .pw_nodes
+            .iter()
+            .any(|node| ctx.profile.drivers.contains_key(&node.id));
+
+        let mut title = format!("{} {}", self.name(), media_type);
+        if ctx.node_lists.is_pinned(self.name()) {
+            title.insert_str(0, "📌 ");
+        }
+        if is_driver {
+            title.insert_str(0, "🕑 ");
+        }
+        if let Some(load) = load {
+            title.push_str(&format!(" | {:.0}%", load * 100.0));
+        }
+        if xruns > 0 {
+            title.push_str(&format!(" ⚠{}", xruns));
+        }
+
+        // A badge is shown for the state of every pipewire node
+        let badges = self
+            .pw_nodes
+            .iter()
+            .filter_map(|node| {
+                let (color, state) = match node.state.as_ref()? {
+                    NodeState::Creating => (egui::Color32::LIGHT_BLUE, "creating"),
+                    NodeState::Suspended => (egui::Color32::DARK_GRAY, "suspended"),
+                    NodeState::Idle => (egui::Color32::YELLOW, "idle"),
+                    NodeState::Running => (egui::Color32::GREEN, "running"),
+                    NodeState::Error(_) => (egui::Color32::RED, "error"),
+                };
+                Some((color, format!("[{}] {}", node.id, state)))
+            })
+            .collect::<Vec<_>>();
+
+        let errors = self
+            .pw_nodes
+            .iter()
+            .filter_map(|node| match &node.state {
+                Some(NodeState::Error(err)) => Some(err.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // Streams show what they are currently playing below the title,
+        // prefixed by their id when several streams of one application share the node
+        let mut media_names = Vec::new();
+        for node in self.pw_nodes.iter() {
+            let media_name = match &node.media_name {
+                Some(media_name) if self.pw_nodes.len() > 1 => {
+                    format!("#{} {}", node.id, media_name)
+                }
+                Some(media_name) => media_name.clone(),
+                None if self.pw_nodes.len() > 1 => format!("#{}", node.id),
+                None => continue,
+            };
+            if !media_names.contains(&media_name) {
+                media_names.push(media_name);
+            }
+        }
+
+        let mut hover_text = self
+            .pw_nodes
+            .iter()
+            .filter_map(|node| {
+                node.icon_name
+                    .as_ref()
+                    .map(|icon_name| format!("[{}] icon {}", node.id, icon_name))
+            })
+            .collect::<Vec<_>>();
+        hover_text.extend(timings);
+
+        let theme = ctx.theme;
+        let port_filters = ctx.port_filters;
+        let node_lists = ctx.node_lists;
         ui_node.with_title(move |ui| {
-            ui.label(
-                egui::RichText::new(format!("{} {}", self.name(), media_type))
-                    .color(theme.text_color),
-            )
+            ui.vertical(|ui| {
+                let response = ui
+                    .horizontal(|ui| {
+                        for (color, state) in badges {
+                            ui.label(egui::RichText::new("●").color(color))
+                                .on_hover_text(state);
+                        }
+                        ui.label(egui::RichText::new(title).color(theme.text_color))
+                    })
+                    .inner;
+
+                for media_name in media_names {
+                    ui.label(
+                        egui::RichText::new(media_name)
+                            .color(theme.text_color)
+                            .small()
+                            .italics(),
+                    );
+                }
+
+                for err in errors {
+                    ui.label(egui::RichText::new(err).color(egui::Color32::RED).small());
+                }
+
+                let response = if hover_text.is_empty() {
+                    response
+                } else {
+                    response.on_hover_text(hover_text.join("\n"))
+                };
+                response.context_menu(|ui| {
+                    port_filters.node_menu(ui, self.name());
+                    ui.separator();
+                    node_lists.node_menu(ui, self.name());
+                })
+            })
+            .inner
             // egui::Label::new(&format!("{} {}", self.name(), media_type))
             //     .text_color(theme.text_color)
             //     .ui(ui)
         });
 
+        let visibility = if expanded {
+            None
+        } else {
+            Some(port_filters.get(self.name()))
+        };
+
+        let mut folded = Vec::new();
         for node in self.pw_nodes.iter() {
-            Self::draw_ports(ui_node, node, theme, debug_view);
+            folded.extend(Self::draw_ports(
+                ui_node,
+                self.name(),
+                node,
+                ctx,
+                visibility,
+            ));
         }
+
+        folded
     }
 }
 
 #[derive(Debug)]
-struct PwNode {
+pub(super) struct PwNode {
     id: u32, //Pipewire id of the node
     description: Option<String>,
     media_type: Option<MediaType>,
+    /// `media.name` of streams, e.g. the title of the track being played
+    media_name: Option<String>,
+    icon_name: Option<String>,
+    client_id: Option<u32>,
+    driver_id: Option<u32>,
+    state: Option<NodeState>,
     ports: HashMap<u32, Port>,
 }
+
+impl PwNode {
+    pub(super) fn port_ids(&self) -> HashSet<u32> {
+        self.ports.keys().copied().collect()
+    }
+    fn link_endpoint<'a>(&self, port: &'a Port) -> LinkEndpoint<'a> {
+        LinkEndpoint {
+            port_type: port.port_type(),
+            media_type: self.media_type,
+            format: port.format(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggle_adds_and_removes() {
+        let lists = NodeLists::default();
+
+        NodeLists::toggle(&lists.pinned, "Speakers");
+        assert!(lists.is_pinned("Speakers"));
+        assert!(!lists.is_pinned("Microphone"));
+
+        NodeLists::toggle(&lists.pinned, "Speakers");
+        assert!(!lists.is_pinned("Speakers"));
+    }
+
+    #[test]
+    fn lists_are_independent() {
+        let lists = NodeLists::default();
+
+        NodeLists::toggle(&lists.hidden, "Speakers");
+        assert!(lists.is_hidden("Speakers"));
+        assert!(!lists.is_pinned("Speakers"));
+    }
+
+    #[test]
+    fn hidden_nodes_are_shown_with_show_hidden() {
+        let mut lists = NodeLists::default();
+        NodeLists::toggle(&lists.hidden, "Speakers");
+
+        assert!(!lists.is_shown("Speakers"));
+        assert!(lists.is_shown("Microphone"));
+
+        lists.show_hidden = true;
+        assert!(lists.is_shown("Speakers"));
+
+        lists.show_hidden = false;
+        NodeLists::toggle(&lists.hidden, "Speakers");
+        assert!(lists.is_shown("Speakers"));
+    }
+
+    #[test]
+    fn lists_survive_serialization() {
+        let lists = NodeLists::default();
+        NodeLists::toggle(&lists.pinned, "Speakers");
+        NodeLists::toggle(&lists.hidden, "Microphone");
+
+        let lists: NodeLists =
+            serde_json::from_str(&serde_json::to_string(&lists).unwrap()).unwrap();
+        assert!(lists.is_pinned("Speakers"));
+        assert!(lists.is_hidden("Microphone"));
+    }
+
+    fn firefox_stream(media_name: &str) -> NodeProperties {
+        let props = [
+            ("media.class", "Stream/Output/Audio"),
+            ("application.name", "Firefox"),
+            ("node.name", "Firefox"),
+            ("media.name", media_name),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        crate::pipewire_impl::node_properties(&props)
+    }
+
+    #[test]
+    fn streams_keep_their_lists_across_reconnects() {
+        let lists = NodeLists::default();
+
+        let mut node = Node::new(Id::new("Firefox"), "Firefox".to_string());
+        for (id, media_name) in [(87, "Video"), (88, "Music")] {
+            let properties = firefox_stream(media_name);
+            assert_eq!(properties.name, node.name());
+            node.add_pw_node(id, properties);
+        }
+        NodeLists::toggle(&lists.pinned, node.name());
+
+        let lists: NodeLists =
+            serde_json::from_str(&serde_json::to_string(&lists).unwrap()).unwrap();
+
+        // The streams come back with new ids once the application reconnects
+        let mut reconnected = Node::new(Id::new("Firefox"), "Firefox".to_string());
+        for (id, media_name) in [(120, "Video"), (121, "Music")] {
+            let properties = firefox_stream(media_name);
+            assert_eq!(Id::new(&properties.name), node.id());
+            reconnected.add_pw_node(id, properties);
+        }
+        assert!(reconnected.has_pw_node(120) && reconnected.has_pw_node(121));
+        assert!(lists.is_pinned(reconnected.name()));
+    }
+}
@@ -0,0 +1,382 @@
+//! Command palette to patch the graph with the keyboard,
+//! e.g. `firefox:output_FL -> speakers:playback_FL` connects two ports and `-x` disconnects them
+
+use crate::pipewire_impl::PortType;
+
+use super::graph::Graph;
+
+/// Maximum number of suggestions shown below the query
+const MAX_SUGGESTIONS: usize = 8;
+
+pub struct PortEntry {
+    pub id: u32,
+    pub node_name: String,
+    pub port_name: String,
+    pub port_type: PortType,
+}
+
+impl PortEntry {
+    /// `node:port`, the syntax used to refer to a port in the palette
+    pub fn path(&self) -> String {
+        format!("{}:{}", self.node_name, self.port_name)
+    }
+}
+
+pub enum PaletteCommand {
+    Connect { from_port: u32, to_port: u32 },
+    Disconnect(u32),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operator {
+    Connect,
+    Disconnect,
+}
+
+impl Operator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Connect => "->",
+            Operator::Disconnect => "-x",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CommandPalette {
+    open: bool,
+    query: String,
+    /// Index of the highlighted suggestion
+    selected: usize,
+    request_focus: bool,
+    /// Keys pressed in the frame the palette was opened in are ignored, as they were used to open it
+    just_opened: bool,
+}
+
+impl CommandPalette {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+    pub fn open(&mut self, query: String) {
+        self.open = true;
+        self.query = query;
+        self.selected = 0;
+        self.request_focus = true;
+        self.just_opened = true;
+    }
+    pub fn close(&mut self) {
+        self.open = false;
+        self.query.clear();
+    }
+
+    /// Splits the query into the source, the operator and the target
+    fn parse(&self) -> (&str, Option<(Operator, &str)>) {
+        let query = self.query.as_str();
+        for operator in [Operator::Connect, Operator::Disconnect] {
+            // Names can contain the operators, like `alsa-xfi`, so they have to be separated from them by whitespace.
+            // The end of the query counts as whitespace, so that the operator is recognised while the target is typed
+            let found = query
+                .match_indices(operator.symbol())
+                .find(|(index, symbol)| {
+                    let before = query[..*index].chars().next_back();
+                    let after = query[index + symbol.len()..].chars().next();
+                    before.map_or(false, char::is_whitespace)
+                        && after.map_or(true, char::is_whitespace)
+                });
+
+            if let Some((index, symbol)) = found {
+                let target = &query[index + symbol.len()..];
+                return (query[..index].trim(), Some((operator, target.trim())));
+            }
+        }
+        (query.trim(), None)
+    }
+
+    /// Shows the palette if it is open, returns a command once one has been entered
+    pub fn show(&mut self, ctx: &egui::CtxRef, graph: &Graph) -> Option<PaletteCommand> {
+        if !self.open {
+            return None;
+        }
+
+        let ports = graph.port_entries();
+        let (source, target) = self.parse();
+
+        let source_matches = fuzzy_matches(source, ports.iter());
+        let suggestions = match target {
+            None => source_matches.clone(),
+            Some((_, target)) => match source_matches.first() {
+                // Only ports of the opposite direction can be linked to the source
+                Some(source) => fuzzy_matches(
+                    target,
+                    ports
+                        .iter()
+                        .filter(|port| is_opposite(port.port_type, source.port_type)),
+                ),
+                None => Vec::new(),
+            },
+        };
+
+        let operator = target.map(|(operator, _)| operator);
+        let source_port = source_matches.first().map(|port| (port.id, port.port_type));
+        let completions = suggestions
+            .iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|port| (port.id, port.path()))
+            .collect::<Vec<_>>();
+
+        let input = ctx.input();
+        let just_opened = std::mem::take(&mut self.just_opened);
+        let key_pressed = |key| !just_opened && input.key_pressed(key);
+
+        if key_pressed(egui::Key::Escape) {
+            self.close();
+            return None;
+        }
+        if key_pressed(egui::Key::ArrowDown) && self.selected + 1 < completions.len() {
+            self.selected += 1;
+        }
+        if key_pressed(egui::Key::ArrowUp) {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(completions.len().saturating_sub(1));
+
+        let complete = key_pressed(egui::Key::Tab);
+        let enter = key_pressed(egui::Key::Enter);
+
+        let mut command = None;
+
+        if let Some((port_id, path)) = completions.get(self.selected) {
+            match (operator, source_port) {
+                (None, _) if complete || enter => {
+                    self.query = format!("{} -> ", path);
+                    self.selected = 0;
+                    self.request_focus = true;
+                }
+                (Some(operator), Some(_)) if complete => {
+                    let (source, _) = self.parse();
+                    self.query = format!("{} {} {}", source, operator.symbol(), path);
+                    self.request_focus = true;
+                }
+                (Some(operator), Some((source_id, source_type))) if enter => {
+                    // Links always go from an output to an input
+                    let (from_port, to_port) = match source_type {
+                        PortType::Input => (*port_id, source_id),
+                        _ => (source_id, *port_id),
+                    };
+
+                    command = match operator {
+                        Operator::Connect => Some(PaletteCommand::Connect { from_port, to_port }),
+                        Operator::Disconnect => match graph.find_link(from_port, to_port) {
+                            Some(link_id) => Some(PaletteCommand::Disconnect(link_id)),
+                            None => {
+                                log::warn!("Ports {} and {} aren't linked", from_port, to_port);
+                                None
+                            }
+                        },
+                    };
+                }
+                _ => {}
+            }
+        }
+
+        if command.is_some() {
+            self.close();
+            return command;
+        }
+
+        let query = &mut self.query;
+        let selected = self.selected;
+        let request_focus = std::mem::take(&mut self.request_focus);
+
+        egui::Window::new("Command palette")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 48.0))
+            .show(ctx, |ui| {
+                ui.set_min_width(400.0);
+
+                let response = ui.add(
+                    egui::TextEdit::singleline(query)
+                        .hint_text("node:port -> node:port")
+                        .desired_width(f32::INFINITY),
+                );
+                if request_focus {
+                    response.request_focus();
+                }
+
+                ui.separator();
+
+                for (ix, (_, path)) in completions.iter().enumerate() {
+                    ui.selectable_label(ix == selected, path);
+                }
+                if completions.is_empty() {
+                    ui.label(egui::RichText::new("No matching ports").weak());
+                }
+
+                ui.label(
+                    egui::RichText::new("[Tab] Complete  [Enter] Run  [Esc] Close")
+                        .weak()
+                        .small(),
+                );
+            });
+
+        None
+    }
+}
+
+fn is_opposite(a: PortType, b: PortType) -> bool {
+    matches!(
+        (a, b),
+        (PortType::Input, PortType::Output) | (PortType::Output, PortType::Input)
+    )
+}
+
+/// Ports whose path matches the query, best matches first
+fn fuzzy_matches<'a>(
+    query: &str,
+    ports: impl Iterator<Item = &'a PortEntry>,
+) -> Vec<&'a PortEntry> {
+    let mut matches = ports
+        .filter_map(|port| {
+            let path = port.path();
+            fuzzy_score(query, &path).map(|score| (score, path.len(), port))
+        })
+        .collect::<Vec<_>>();
+
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    matches.into_iter().map(|(_, _, port)| port).collect()
+}
+
+/// Scores how well `query` matches `candidate` as a case insensitive subsequence, higher is better.
+/// Consecutive characters and characters at the start of a word score higher
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate = candidate.to_lowercase().chars().collect::<Vec<_>>();
+
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match = None;
+
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let offset = candidate[position..].iter().position(|&other| other == c)?;
+        let index = position + offset;
+
+        score += 1;
+        if previous_match.map_or(false, |previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || matches!(candidate[index - 1], ':' | '_' | '-' | ' ' | '.') {
+            score += 3;
+        }
+
+        previous_match = Some(index);
+        position = index + 1;
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(id: u32, node_name: &str, port_name: &str) -> PortEntry {
+        PortEntry {
+            id,
+            node_name: node_name.to_string(),
+            port_name: port_name.to_string(),
+            port_type: PortType::Output,
+        }
+    }
+
+    #[test]
+    fn fuzzy_score_table() {
+        let cases = [
+            ("", "speakers:playback_FL", Some(0)),
+            ("spk", "speakers:playback_FL", Some(11)),
+            ("FL", "speakers:playback_FL", Some(10)),
+            ("s p", "speakers:playback_FL", Some(10)),
+            ("ffx", "firefox", Some(6)),
+            ("xyz", "speakers:playback_FL", None),
+            ("ba", "ab", None),
+        ];
+
+        for (query, candidate, expected) in cases {
+            assert_eq!(
+                fuzzy_score(query, candidate),
+                expected,
+                "{:?} in {:?}",
+                query,
+                candidate
+            );
+        }
+    }
+
+    #[test]
+    fn fuzzy_matches_rank_best_and_shortest_first() {
+        let ports = [
+            port(1, "Speakers", "playback_FR"),
+            port(2, "Firefox", "output_FL"),
+            port(3, "Speakers", "playback_FL"),
+            port(4, "Speakers", "monitor_FL"),
+        ];
+
+        let ids = |query| {
+            fuzzy_matches(query, ports.iter())
+                .into_iter()
+                .map(|port| port.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("speakers:pfl"), vec![3]);
+        // Word starts and consecutive characters score higher, ties go to the shorter path
+        assert_eq!(ids("fl"), vec![4, 3, 2]);
+        assert!(ids("mic").is_empty());
+    }
+
+    #[test]
+    fn parse_table() {
+        let cases = [
+            ("firefox:out", ("firefox:out", None)),
+            ("  firefox  ", ("firefox", None)),
+            (
+                "firefox:output_FL -> speakers",
+                ("firefox:output_FL", Some((Operator::Connect, "speakers"))),
+            ),
+            ("a ->", ("a", Some((Operator::Connect, "")))),
+            ("a->b", ("a->b", None)),
+            ("a -x b", ("a", Some((Operator::Disconnect, "b")))),
+            (
+                "null-sink:monitor_FL -x b",
+                ("null-sink:monitor_FL", Some((Operator::Disconnect, "b"))),
+            ),
+            // Operators inside names aren't operators
+            ("alsa-xfi:capture_FL", ("alsa-xfi:capture_FL", None)),
+            (
+                "my-xsink -x",
+                ("my-xsink", Some((Operator::Disconnect, ""))),
+            ),
+            (
+                "alsa-xfi:capture_FL -> my-xsink:playback_FL",
+                (
+                    "alsa-xfi:capture_FL",
+                    Some((Operator::Connect, "my-xsink:playback_FL")),
+                ),
+            ),
+            (
+                "my-xsink:monitor_FL -x alsa-xfi:playback_FL",
+                (
+                    "my-xsink:monitor_FL",
+                    Some((Operator::Disconnect, "alsa-xfi:playback_FL")),
+                ),
+            ),
+        ];
+
+        let mut palette = CommandPalette::default();
+        for (query, expected) in cases {
+            palette.open(query.to_string());
+            assert!(palette.parse() == expected, "{:?}", query);
+        }
+    }
+}
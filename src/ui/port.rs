@@ -1,26 +1,343 @@
-use crate::pipewire_impl::PortType;
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, iter::Peekable, str::Chars};
+
+use serde::{Deserialize, Serialize};
+
+use crate::pipewire_impl::{PortProperties, PortType};
+
+/// Channel positions in the order they are defined by SPA, AUX channels are placed after these
+const CHANNEL_POSITIONS: &[&str] = &[
+    "MONO", "FL", "FR", "FC", "LFE", "SL", "SR", "FLC", "FRC", "RC", "RL", "RR", "TC", "TFL",
+    "TFC", "TFR", "TRL", "TRC", "TRR", "RLC", "RRC", "FLW", "FRW", "LFE2", "FLH", "FCH", "FRH",
+    "TFLC", "TFRC", "TSL", "TSR", "LLFE", "RLFE", "BC", "BLC", "BRC",
+];
+
+/// Ports of a node are drawn grouped into these sections, in this order
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PortSection {
+    Input,
+    Output,
+    Monitor,
+}
+
+impl PortSection {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PortSection::Input => "Inputs",
+            PortSection::Output => "Outputs",
+            PortSection::Monitor => "Monitors",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Port {
     pub id: u32,
     pub name: String,
+    pub alias: Option<String>,
+    pub channel: Option<String>,
+    pub index: Option<u32>,
+    pub monitor: bool,
     pub port_type: PortType,
+    pub format: Option<String>,
 }
 impl Port {
-    pub fn new(id: u32, name: String, port_type: PortType) -> Self {
+    pub fn new(id: u32, properties: PortProperties) -> Self {
+        let PortProperties {
+            name,
+            alias,
+            channel,
+            index,
+            monitor,
+            port_type,
+            format,
+        } = properties;
+
         Self {
             id,
             name,
+            alias,
+            channel,
+            index,
+            monitor,
             port_type,
+            format,
         }
     }
+    pub fn update(&mut self, properties: PortProperties) {
+        *self = Self::new(self.id, properties);
+    }
     pub fn id(&self) -> u32 {
         self.id
     }
     pub fn name(&self) -> &str {
         &self.name
     }
+    /// The alias of the port if it has one, otherwise its name
+    pub fn display_name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
     pub fn port_type(&self) -> PortType {
         self.port_type
     }
+    pub fn format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+    pub fn section(&self) -> PortSection {
+        match self.port_type {
+            PortType::Input => PortSection::Input,
+            PortType::Output | PortType::Unknown if self.monitor => PortSection::Monitor,
+            PortType::Output | PortType::Unknown => PortSection::Output,
+        }
+    }
+    fn channel_position(&self) -> Option<usize> {
+        let channel = self.channel.as_deref()?;
+
+        CHANNEL_POSITIONS
+            .iter()
+            .position(|position| *position == channel)
+            .or_else(|| {
+                let aux = channel.strip_prefix("AUX")?.parse::<usize>().ok()?;
+                Some(CHANNEL_POSITIONS.len() + aux)
+            })
+    }
+    /// Orders ports by section, then channel position, then port index, falling back to a natural sort of the names
+    pub fn display_order(&self, other: &Port) -> Ordering {
+        fn none_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+
+        self.section()
+            .cmp(&other.section())
+            .then_with(|| none_last(self.channel_position(), other.channel_position()))
+            .then_with(|| none_last(self.index, other.index))
+            .then_with(|| natural_cmp(self.name(), other.name()))
+    }
+}
+
+/// Compares strings treating runs of digits as numbers, so that `AUX2` comes before `AUX10`
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn take_number(chars: &mut Peekable<Chars<'_>>) -> String {
+        let mut digits = String::new();
+        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            digits.push(c);
+        }
+        digits
+    }
+
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+
+                let x_trimmed = x.trim_start_matches('0');
+                let y_trimmed = y.trim_start_matches('0');
+
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed))
+                    .then_with(|| x.len().cmp(&y.len()));
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Labels assigned to ports by the user, these are keyed by node and port name so that they survive restarts
+/// They are exported along with the links, so that they can be restored with them
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PortLabels {
+    labels: HashMap<String, String>,
+
+    /// Key and current text of the label being edited
+    /// Edits are requested while drawing the nodes, which only have shared access to the labels
+    #[serde(skip)]
+    editing: RefCell<Option<(String, String)>>,
+}
+
+impl PortLabels {
+    fn key(node_name: &str, port_name: &str) -> String {
+        format!("{}:{}", node_name, port_name)
+    }
+    pub fn get(&self, node_name: &str, port_name: &str) -> Option<&str> {
+        self.labels
+            .get(&Self::key(node_name, port_name))
+            .map(String::as_str)
+    }
+    /// Labels keyed by `<node>:<port>` paths
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+    /// Adds labels keyed by `<node>:<port>` paths, replacing the current labels of those ports
+    pub fn extend(&mut self, labels: impl IntoIterator<Item = (String, String)>) {
+        self.labels.extend(labels);
+    }
+    pub fn request_edit(&self, node_name: &str, port_name: &str, label: &str) {
+        *self.editing.borrow_mut() = Some((Self::key(node_name, port_name), label.to_string()));
+    }
+    pub fn edit_window(&mut self, ctx: &egui::CtxRef) {
+        let editing = self.editing.get_mut();
+        let (key, label) = match editing {
+            Some(editing) => editing,
+            None => return,
+        };
+
+        let mut open = true;
+        // Some(None) resets the label back to the port alias/name
+        let mut result = None;
+
+        egui::Window::new("Rename port")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(key.as_str());
+                let response = ui.text_edit_singleline(label);
+
+                ui.horizontal(|ui| {
+                    let submitted =
+                        response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+                    if ui.button("Ok").clicked() || submitted {
+                        result = Some(Some(label.clone()));
+                    }
+                    if ui.button("Reset").clicked() {
+                        result = Some(None);
+                    }
+                });
+            });
+
+        match result {
+            Some(Some(label)) if !label.is_empty() => {
+                self.labels.insert(key.clone(), label);
+            }
+            Some(_) => {
+                self.labels.remove(key.as_str());
+            }
+            None if open => return,
+            None => {}
+        }
+
+        *editing = None;
+    }
+}
+
+/// Which ports of a node are folded into its bus pins
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortVisibility {
+    pub hide_monitor: bool,
+    pub hide_unlinked: bool,
+    pub collapse_all: bool,
+}
+
+impl PortVisibility {
+    pub fn is_hidden(&self, port: &Port, linked: bool) -> bool {
+        self.collapse_all || (self.hide_monitor && port.monitor) || (self.hide_unlinked && !linked)
+    }
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.checkbox(&mut self.hide_monitor, "Hide monitor ports")
+            .changed()
+            | ui.checkbox(&mut self.hide_unlinked, "Hide unlinked ports")
+                .changed()
+            | ui.checkbox(&mut self.collapse_all, "Collapse all ports")
+                .changed()
+    }
+}
+
+/// Global port visibility, which can be overridden per node
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PortFilters {
+    pub global: PortVisibility,
+
+    /// Keyed by node name, changed from the context menu of a node while it is being drawn
+    nodes: RefCell<HashMap<String, PortVisibility>>,
+}
+
+impl PortFilters {
+    pub fn get(&self, node_name: &str) -> PortVisibility {
+        self.nodes
+            .borrow()
+            .get(node_name)
+            .copied()
+            .unwrap_or(self.global)
+    }
+    /// Context menu contents for overriding the visibility of a single node
+    pub fn node_menu(&self, ui: &mut egui::Ui, node_name: &str) {
+        let mut visibility = self.get(node_name);
+        if visibility.ui(ui) {
+            self.nodes
+                .borrow_mut()
+                .insert(node_name.to_string(), visibility);
+        }
+
+        let overridden = self.nodes.borrow().contains_key(node_name);
+        if ui
+            .add_enabled(overridden, egui::Button::new("Use global settings"))
+            .clicked()
+        {
+            self.nodes.borrow_mut().remove(node_name);
+            ui.close_menu();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_table() {
+        let cases = [
+            ("AUX2", "AUX10", Ordering::Less),
+            ("AUX10", "AUX2", Ordering::Greater),
+            ("AUX1", "AUX1", Ordering::Equal),
+            ("AUX", "AUX1", Ordering::Less),
+            ("playback_FL", "playback_FR", Ordering::Less),
+            ("capture_1", "playback_1", Ordering::Less),
+            ("a2b", "a2c", Ordering::Less),
+            // Leading zeros only break ties
+            ("AUX01", "AUX1", Ordering::Greater),
+            ("AUX01", "AUX2", Ordering::Less),
+            ("00", "0", Ordering::Greater),
+            // Numbers are compared by their digits, so they can't overflow
+            (
+                "99999999999999999999",
+                "100000000000000000000",
+                Ordering::Less,
+            ),
+        ];
+
+        for (a, b, expected) in cases {
+            assert_eq!(natural_cmp(a, b), expected, "{:?} vs {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn natural_cmp_sorts_channels() {
+        let mut names = vec!["AUX10", "AUX2", "AUX1", "AUX0", "AUX11", "AUX3"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+
+        assert_eq!(names, ["AUX0", "AUX1", "AUX2", "AUX3", "AUX10", "AUX11"]);
+    }
 }
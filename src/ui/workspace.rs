@@ -0,0 +1,226 @@
+//! Named layouts of the graph, which are switched between using tabs above the canvas
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Workspace {
+    pub name: String,
+    /// Grid space positions of nodes, keyed by node name
+    pub positions: HashMap<String, egui::Pos2>,
+    /// Comma separated parts of node names, only matching nodes are shown. Every node is shown if it's empty
+    pub filter: String,
+    /// The canvas can't be zoomed, so only its panning is kept
+    pub panning: egui::Vec2,
+}
+
+impl Workspace {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            positions: HashMap::new(),
+            filter: String::new(),
+            panning: egui::Vec2::ZERO,
+        }
+    }
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self::new("Everything".to_string())
+    }
+}
+
+/// Requested by clicking on the tabs
+pub enum WorkspaceAction {
+    Switch(usize),
+    Add,
+    Remove(usize),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Workspaces {
+    workspaces: Vec<Workspace>,
+    current: usize,
+}
+
+impl Default for Workspaces {
+    fn default() -> Self {
+        Self {
+            workspaces: vec![Workspace::default()],
+            current: 0,
+        }
+    }
+}
+
+impl Workspaces {
+    pub fn current(&self) -> &Workspace {
+        &self.workspaces[self.current]
+    }
+    pub fn current_mut(&mut self) -> &mut Workspace {
+        &mut self.workspaces[self.current]
+    }
+    /// Makes sure there is a current workspace, the saved workspaces may have been edited by hand
+    pub fn repair(&mut self) {
+        if self.workspaces.is_empty() {
+            self.workspaces.push(Workspace::default());
+        }
+        self.current = self.current.min(self.workspaces.len() - 1);
+    }
+    pub fn switch(&mut self, index: usize) {
+        self.current = index.min(self.workspaces.len() - 1);
+    }
+    /// Adds a new empty workspace, returning its index
+    pub fn add(&mut self) -> usize {
+        let name = format!("Workspace {}", self.workspaces.len() + 1);
+        self.workspaces.push(Workspace::new(name));
+
+        self.workspaces.len() - 1
+    }
+    /// Removes a workspace, the last one can't be removed
+    pub fn remove(&mut self, index: usize) {
+        if self.workspaces.len() > 1 && index < self.workspaces.len() {
+            self.workspaces.remove(index);
+            if self.current >= index && self.current > 0 {
+                self.current -= 1;
+            }
+        }
+    }
+
+    pub fn tabs(&mut self, ui: &mut egui::Ui) -> Option<WorkspaceAction> {
+        let mut action = None;
+        let removable = self.workspaces.len() > 1;
+
+        ui.horizontal(|ui| {
+            for (ix, workspace) in self.workspaces.iter_mut().enumerate() {
+                let tab = ui
+                    .selectable_label(ix == self.current, &workspace.name)
+                    .on_hover_text("Right click to rename")
+                    .context_menu(|ui| {
+                        ui.text_edit_singleline(&mut workspace.name);
+                        if ui
+                            .add_enabled(removable, egui::Button::new("Remove"))
+                            .clicked()
+                        {
+                            action = Some(WorkspaceAction::Remove(ix));
+                            ui.close_menu();
+                        }
+                    });
+                if tab.clicked() {
+                    action = Some(WorkspaceAction::Switch(ix));
+                }
+            }
+
+            if ui
+                .small_button("+")
+                .on_hover_text("New workspace")
+                .clicked()
+            {
+                action = Some(WorkspaceAction::Add);
+            }
+
+            ui.separator();
+            ui.label("Show");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.workspaces[self.current].filter)
+                    .hint_text("All nodes, or parts of names separated by commas"),
+            );
+        });
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out the current workspace as the graph would store it
+    fn store(workspaces: &mut Workspaces, node: &str, filter: &str, panning: egui::Vec2) {
+        let workspace = workspaces.current_mut();
+        workspace
+            .positions
+            .insert(node.to_string(), egui::pos2(10.0, 20.0));
+        workspace.filter = filter.to_string();
+        workspace.panning = panning;
+    }
+
+    #[test]
+    fn switching_restores_each_layout() {
+        let mut workspaces = Workspaces::default();
+        store(&mut workspaces, "Speakers", "", egui::vec2(-40.0, 15.0));
+
+        let index = workspaces.add();
+        workspaces.switch(index);
+        let workspace = workspaces.current();
+        assert!(workspace.positions.is_empty());
+        assert!(workspace.filter.is_empty());
+        assert_eq!(workspace.panning, egui::Vec2::ZERO);
+
+        store(
+            &mut workspaces,
+            "Firefox",
+            "firefox, speakers",
+            egui::vec2(5.0, 5.0),
+        );
+        workspaces.switch(0);
+
+        let workspace = workspaces.current();
+        assert_eq!(workspace.positions.keys().collect::<Vec<_>>(), ["Speakers"]);
+        assert_eq!(workspace.filter, "");
+        assert_eq!(workspace.panning, egui::vec2(-40.0, 15.0));
+    }
+
+    #[test]
+    fn layouts_survive_serialization() {
+        let mut workspaces = Workspaces::default();
+        store(&mut workspaces, "Speakers", "", egui::vec2(-40.0, 15.0));
+        let index = workspaces.add();
+        workspaces.switch(index);
+        store(&mut workspaces, "Firefox", "firefox", egui::vec2(5.0, 5.0));
+
+        let mut workspaces: Workspaces =
+            serde_json::from_str(&serde_json::to_string(&workspaces).unwrap()).unwrap();
+
+        let workspace = workspaces.current();
+        assert_eq!(workspace.name, "Workspace 2");
+        assert_eq!(
+            workspace.positions.get("Firefox"),
+            Some(&egui::pos2(10.0, 20.0))
+        );
+        assert_eq!(workspace.filter, "firefox");
+        assert_eq!(workspace.panning, egui::vec2(5.0, 5.0));
+
+        workspaces.switch(0);
+        assert_eq!(workspaces.current().panning, egui::vec2(-40.0, 15.0));
+    }
+
+    #[test]
+    fn removing_keeps_the_current_workspace() {
+        let mut workspaces = Workspaces::default();
+        workspaces.add();
+        let index = workspaces.add();
+        workspaces.switch(index);
+        store(&mut workspaces, "Firefox", "firefox", egui::vec2(5.0, 5.0));
+
+        workspaces.remove(0);
+        assert_eq!(workspaces.current().name, "Workspace 3");
+        assert_eq!(workspaces.current().panning, egui::vec2(5.0, 5.0));
+
+        // The last workspace can't be removed
+        workspaces.remove(0);
+        workspaces.remove(0);
+        assert_eq!(workspaces.current().name, "Workspace 3");
+    }
+
+    #[test]
+    fn repair_restores_a_current_workspace() {
+        let mut workspaces: Workspaces =
+            serde_json::from_str(r#"{"workspaces": [], "current": 3}"#).unwrap();
+        workspaces.repair();
+        assert_eq!(workspaces.current().name, "Everything");
+    }
+}